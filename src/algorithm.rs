@@ -0,0 +1,127 @@
+use std::time::Duration;
+
+use crate::{errors::Error, rule::Rule};
+
+/// Rate limiting algorithm applied by the backend.
+///
+/// The limiter's `limit` and `period` set the sustained rate for every algorithm.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Algorithm {
+    /// Counts hits in consecutive windows of `period`, starting at a key's first hit.
+    ///
+    /// Cheapest option (a single counter per key), but a client can make up to twice the limit
+    /// in quick succession around a window boundary.
+    #[default]
+    FixedWindow,
+
+    /// Records a timestamp per accepted hit and counts those within the trailing `period`.
+    ///
+    /// Exact, with no boundary bursts, at the cost of storing up to `limit` entries per key.
+    SlidingLog,
+
+    /// Approximates a sliding window from two fixed window counters, weighting the previous
+    /// window's count by how much of it still overlaps the trailing `period`.
+    ///
+    /// Smooths out boundary bursts with only two counters per key; assumes hits were evenly
+    /// spread over the previous window.
+    SlidingWindow,
+
+    /// Token bucket holding up to `capacity` tokens, refilled continuously with `limit` tokens
+    /// per `period`; each hit takes one token.
+    ///
+    /// Allows bursts of `capacity` hits followed by the sustained refill rate. Extra rules, e.g.
+    /// from [`Builder::rule`](crate::Builder::rule), are buckets of their own `limit` tokens. See
+    /// [`Builder::token_bucket`](crate::Builder::token_bucket).
+    TokenBucket {
+        /// Maximum number of tokens, i.e. the largest burst.
+        capacity: usize,
+    },
+
+    /// Generic cell rate algorithm: spaces hits `period / limit` apart, tolerating bursts of up
+    /// to `limit` hits.
+    ///
+    /// Behaves like a token bucket of `limit` tokens while storing a single timestamp (the
+    /// theoretical arrival time) per key, and reports a precise
+    /// [`retry_after`](crate::Status::retry_after) when rejecting.
+    Gcra,
+
+    /// Leaky bucket queue: hits are scheduled `period / limit` apart and delayed until their slot
+    /// instead of being rejected, unless the delay would exceed `max_wait`.
+    ///
+    /// The [`RateLimiter`](crate::RateLimiter) middleware sleeps for the
+    /// [`delay`](crate::Status::delay) before handling the request. See
+    /// [`Builder::leaky_bucket`](crate::Builder::leaky_bucket).
+    LeakyBucket {
+        /// Longest a hit may wait for its slot.
+        max_wait: Duration,
+    },
+}
+
+impl Algorithm {
+    /// Returns the most hits a key can make at once, reported as the status limit.
+    ///
+    /// For [`Algorithm::LeakyBucket`] this is the number of hits that fit in the queue.
+    pub(crate) fn capacity(self, limit: usize, period: Duration) -> usize {
+        match self {
+            Algorithm::TokenBucket { capacity } => capacity,
+            Algorithm::LeakyBucket { max_wait } if limit > 0 => {
+                let interval = emission_interval(limit, period).as_nanos().max(1);
+                (max_wait.as_nanos() / interval) as usize + 1
+            }
+            _ => limit,
+        }
+    }
+
+    /// Returns the capacity of the rule at `index`: a token bucket's `capacity` only sizes the
+    /// first rule's bucket, and the others hold their own `limit` of tokens.
+    pub(crate) fn rule_capacity(self, index: usize, rule: &Rule) -> usize {
+        match self {
+            Algorithm::TokenBucket { .. } if index > 0 => rule.limit(),
+            _ => self.capacity(rule.limit(), rule.period()),
+        }
+    }
+
+    /// Checks that `rules` can be enforced: there must be at least one rule, every period must be
+    /// longer than zero, and a token bucket must refill at least one token per interval.
+    pub(crate) fn check_rules(self, rules: &[Rule]) -> Result<(), Error> {
+        if rules.is_empty() {
+            return Err(Error::Other("No rate limit rules".to_owned()));
+        }
+
+        if matches!(self, Algorithm::TokenBucket { .. })
+            && rules
+                .iter()
+                .any(|rule| rule.limit() == 0 || rule.period().is_zero())
+        {
+            return Err(Error::Other(
+                "Token bucket refill and interval must be greater than zero".to_owned(),
+            ));
+        }
+
+        if rules.iter().any(|rule| rule.period().is_zero()) {
+            return Err(Error::Other(
+                "Rate limit periods must be greater than zero".to_owned(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the algorithm's name, used to namespace storage keys.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Algorithm::FixedWindow => "fixed_window",
+            Algorithm::SlidingLog => "sliding_log",
+            Algorithm::SlidingWindow => "sliding_window",
+            Algorithm::TokenBucket { .. } => "token_bucket",
+            Algorithm::Gcra => "gcra",
+            Algorithm::LeakyBucket { .. } => "leaky_bucket",
+        }
+    }
+}
+
+/// Returns the spacing between hits at a sustained rate of `limit` per `period`.
+pub(crate) fn emission_interval(limit: usize, period: Duration) -> Duration {
+    Duration::from_nanos((period.as_nanos() / limit.max(1) as u128) as u64)
+}
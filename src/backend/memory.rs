@@ -0,0 +1,909 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::{BuildHasher as _, RandomState},
+    sync::{Arc, Mutex, Weak},
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{Backend, BoxFuture, rule_key, verdict};
+use crate::{
+    algorithm::{Algorithm, emission_interval},
+    errors::Error,
+    rule::Rule,
+    status::Status,
+};
+
+/// Default number of independently locked shards.
+const DEFAULT_SHARDS: usize = 16;
+
+/// Default upper bound on the number of tracked keys.
+const DEFAULT_MAX_KEYS: usize = 100_000;
+
+/// Default interval between background eviction sweeps.
+const DEFAULT_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Per-rule algorithm state.
+#[derive(Debug, Clone)]
+enum State {
+    /// Fresh or expired key.
+    Empty,
+    Window {
+        count: usize,
+    },
+    Log(VecDeque<Instant>),
+    Buckets {
+        window: u128,
+        current: usize,
+        previous: usize,
+    },
+    Tokens {
+        tokens: f64,
+        updated: Instant,
+    },
+    Tat(Instant),
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    state: State,
+    expires_at: Instant,
+}
+
+impl Entry {
+    /// Fixed window counter, same semantics as the Redis script: add the cost unless over the
+    /// limit, starting a new window when the previous one has expired.
+    fn fixed_window(
+        &mut self,
+        now: Instant,
+        limit: usize,
+        period: Duration,
+        cost: usize,
+    ) -> (usize, Duration) {
+        if !matches!(self.state, State::Window { .. }) {
+            self.state = State::Window { count: 0 };
+            self.expires_at = now + period;
+        }
+        let State::Window { count } = &mut self.state else {
+            unreachable!()
+        };
+
+        let hit = *count + cost;
+        if hit <= limit {
+            *count = hit;
+        }
+        (hit, self.expires_at - now)
+    }
+
+    /// Sliding log, same semantics as the Redis script: rejected hits are not recorded.
+    fn sliding_log(
+        &mut self,
+        now: Instant,
+        limit: usize,
+        period: Duration,
+        cost: usize,
+    ) -> (usize, Duration) {
+        if !matches!(self.state, State::Log(_)) {
+            self.state = State::Log(VecDeque::new());
+        }
+        let State::Log(log) = &mut self.state else {
+            unreachable!()
+        };
+
+        while log.front().is_some_and(|at| *at + period <= now) {
+            log.pop_front();
+        }
+
+        let count = log.len() + cost;
+        if count <= limit {
+            log.extend(std::iter::repeat_n(now, cost));
+            self.expires_at = now + period;
+        }
+
+        // rejected hits wait for enough entries to leave the window
+        let rank = count.saturating_sub(limit + 1);
+        let reset = log.get(rank).map_or(period, |at| *at + period - now);
+        (count, reset)
+    }
+
+    /// Sliding window counter, same semantics as the Redis script with windows aligned to
+    /// `origin` instead of the UNIX epoch.
+    fn sliding_window(
+        &mut self,
+        now: Instant,
+        origin: Instant,
+        limit: usize,
+        period: Duration,
+        cost: usize,
+    ) -> (usize, Duration) {
+        let win = period.as_millis().max(1);
+        let since_origin = (now - origin).as_millis();
+        let (window, elapsed) = (since_origin / win, since_origin % win);
+
+        let (current, previous) = match self.state {
+            State::Buckets {
+                window: w,
+                current,
+                previous,
+            } if w == window => (current, previous),
+            State::Buckets {
+                window: w, current, ..
+            } if w + 1 == window => (0, current),
+            _ => (0, 0),
+        };
+
+        let (win, elapsed) = (win as f64, elapsed as f64);
+        let (cur, prev, lim, c) = (current as f64, previous as f64, limit as f64, cost as f64);
+        let count = (prev * (win - elapsed) / win + cur).ceil() as usize + cost;
+
+        let mut current = current;
+        let reset = if count <= limit {
+            current += cost;
+            win - elapsed
+        } else if cost > limit {
+            win - elapsed
+        } else if current + cost <= limit {
+            (win - (lim - c - cur) * win / prev).ceil() - elapsed
+        } else {
+            win - elapsed + (win - (lim - c) * win / cur).ceil()
+        };
+
+        self.state = State::Buckets {
+            window,
+            current,
+            previous,
+        };
+        self.expires_at = now + Duration::from_millis((2.0 * win - elapsed) as u64);
+
+        (count, Duration::from_millis(reset as u64))
+    }
+
+    /// Token bucket, same semantics as the Redis script.
+    fn token_bucket(
+        &mut self,
+        now: Instant,
+        capacity: usize,
+        refill: usize,
+        interval: Duration,
+        cost: usize,
+    ) -> (usize, Duration) {
+        let rate = refill as f64 / interval.as_millis().max(1) as f64;
+        let cap = capacity as f64;
+
+        let mut tokens = match self.state {
+            State::Tokens { tokens, updated } => {
+                (tokens + (now - updated).as_secs_f64() * 1000.0 * rate).min(cap)
+            }
+            _ => cap,
+        };
+
+        let allowed = tokens >= cost as f64;
+        if allowed {
+            tokens -= cost as f64;
+        }
+
+        let full = ((cap - tokens) / rate).ceil();
+        self.state = State::Tokens {
+            tokens,
+            updated: now,
+        };
+        self.expires_at = now + Duration::from_millis(full.max(1.0) as u64);
+
+        if allowed {
+            (
+                capacity - tokens.floor() as usize,
+                Duration::from_millis(full as u64),
+            )
+        } else {
+            let next = ((cost as f64 - tokens) / rate).ceil();
+            (capacity + 1, Duration::from_millis(next as u64))
+        }
+    }
+
+    /// GCRA, same semantics as the Redis script.
+    fn gcra(
+        &mut self,
+        now: Instant,
+        limit: usize,
+        period: Duration,
+        cost: usize,
+    ) -> (usize, Duration) {
+        if limit == 0 {
+            return (1, period);
+        }
+
+        let interval = emission_interval(limit, period);
+        let tat = match self.state {
+            State::Tat(tat) => tat.max(now),
+            _ => now,
+        };
+        let new_tat = tat + intervals(interval, cost);
+        let ahead = new_tat - now;
+
+        if ahead > period {
+            return (limit + 1, ahead - period);
+        }
+
+        self.state = State::Tat(new_tat);
+        self.expires_at = new_tat;
+
+        let remaining = (period - ahead).as_nanos() / interval.as_nanos().max(1);
+        let used = limit - (remaining as usize).min(limit);
+        (used.max(cost), ahead)
+    }
+
+    /// Returns the next free leaky bucket slot, if any.
+    fn next_slot(&self) -> Option<Instant> {
+        match self.state {
+            State::Tat(next) => Some(next),
+            _ => None,
+        }
+    }
+
+    /// Leaky bucket queue, same semantics as the Redis script: the hit is scheduled at `slot`,
+    /// the latest next free slot of all rules.
+    fn leaky_bucket(
+        &mut self,
+        now: Instant,
+        slot: Instant,
+        limit: usize,
+        period: Duration,
+        max_wait: Duration,
+        cost: usize,
+    ) -> (usize, Duration) {
+        if limit == 0 {
+            return (1, period);
+        }
+
+        let capacity = Algorithm::LeakyBucket { max_wait }.capacity(limit, period);
+        let interval = emission_interval(limit, period);
+        let delay = slot - now;
+
+        if delay > max_wait {
+            return (capacity + 1, delay - max_wait);
+        }
+
+        let reserved = intervals(interval, cost);
+        self.state = State::Tat(slot + reserved);
+        self.expires_at = slot + reserved;
+
+        let queued = delay.as_nanos().div_ceil(interval.as_nanos().max(1)) as usize + cost;
+        (queued.min(capacity), delay + reserved)
+    }
+}
+
+/// Returns `cost` emission intervals.
+fn intervals(interval: Duration, cost: usize) -> Duration {
+    interval.saturating_mul(u32::try_from(cost).unwrap_or(u32::MAX))
+}
+
+/// Entries by rule storage key; the rules of a key share the shard of the key.
+type Shard = Mutex<HashMap<String, Entry>>;
+
+#[derive(Debug)]
+struct Shards {
+    shards: Box<[Shard]>,
+    capacity: usize,
+    hasher: RandomState,
+    origin: Instant,
+}
+
+impl Shards {
+    fn shard(&self, key: &str) -> &Shard {
+        let idx = self.hasher.hash_one(key) as usize % self.shards.len();
+        &self.shards[idx]
+    }
+
+    /// Drops every expired key.
+    fn sweep(&self) {
+        let now = Instant::now();
+        for shard in self.shards.iter() {
+            shard
+                .lock()
+                .unwrap()
+                .retain(|_, entry| entry.expires_at > now);
+        }
+    }
+
+    /// Makes room for a new entry in a full shard.
+    ///
+    /// Expired entries go first; if that is not enough, the entry closest to expiring is evicted.
+    fn evict(entries: &mut HashMap<String, Entry>, capacity: usize, now: Instant) {
+        entries.retain(|_, entry| entry.expires_at > now);
+        if entries.len() < capacity {
+            return;
+        }
+
+        if let Some(key) = entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.expires_at)
+            .map(|(key, _)| key.clone())
+        {
+            entries.remove(&key);
+        }
+    }
+
+    /// Runs `f` on the entries of `rules` for `key`, in rule order, under the shard lock of
+    /// `key`, resetting expired entries first.
+    fn update<R>(
+        &self,
+        key: &str,
+        rules: &[Rule],
+        f: impl FnOnce(&mut [Entry], Instant) -> R,
+    ) -> R {
+        let now = Instant::now();
+        let mut shard = self.shard(key).lock().unwrap();
+
+        let keys: Vec<String> = (0..)
+            .zip(rules)
+            .map(|(i, rule)| rule_key(key, i, rule))
+            .collect();
+        let mut entries: Vec<Entry> = keys
+            .iter()
+            .map(|key| match shard.remove(key) {
+                Some(entry) if entry.expires_at > now => entry,
+                _ => Entry {
+                    state: State::Empty,
+                    expires_at: now,
+                },
+            })
+            .collect();
+
+        let res = f(&mut entries, now);
+
+        // entries left without state, e.g. of keys that were only peeked at, are not stored, so
+        // reading a key never evicts another
+        for (key, entry) in keys.into_iter().zip(entries) {
+            if entry.expires_at <= now {
+                continue;
+            }
+            if shard.len() >= self.capacity {
+                Self::evict(&mut shard, self.capacity, now);
+            }
+            shard.insert(key, entry);
+        }
+
+        res
+    }
+}
+
+/// In-process backend for single-instance deployments and tests.
+///
+/// Counters live in a sharded map bounded by [`max_keys`](MemoryBackendBuilder::max_keys) and
+/// are swept by a background thread. Clones share the same counters.
+#[derive(Debug, Clone)]
+pub struct MemoryBackend {
+    shards: Arc<Shards>,
+}
+
+impl MemoryBackend {
+    /// Constructs an in-memory backend with defaults.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::builder().build()
+    }
+
+    /// Constructs an in-memory backend builder with defaults.
+    #[must_use]
+    pub fn builder() -> MemoryBackendBuilder {
+        MemoryBackendBuilder {
+            shards: DEFAULT_SHARDS,
+            max_keys: DEFAULT_MAX_KEYS,
+            sweep_interval: DEFAULT_SWEEP_INTERVAL,
+        }
+    }
+}
+
+impl Default for MemoryBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn hit<'a>(
+        &'a self,
+        key: &'a str,
+        algorithm: Algorithm,
+        rules: &'a [Rule],
+        cost: usize,
+    ) -> BoxFuture<'a, Result<Status, Error>> {
+        Box::pin(async move {
+            let outcomes = self.shards.update(key, rules, |entries, now| {
+                // leaky bucket rules share a single queue slot
+                let slot = entries
+                    .iter()
+                    .filter_map(Entry::next_slot)
+                    .fold(now, Instant::max);
+
+                let hit = |entry: &mut Entry, index: usize, rule: &Rule| {
+                    let (limit, period) = (rule.limit(), rule.period());
+                    match algorithm {
+                        Algorithm::FixedWindow => entry.fixed_window(now, limit, period, cost),
+                        Algorithm::SlidingLog => entry.sliding_log(now, limit, period, cost),
+                        Algorithm::SlidingWindow => {
+                            entry.sliding_window(now, self.shards.origin, limit, period, cost)
+                        }
+                        Algorithm::TokenBucket { .. } => {
+                            let capacity = algorithm.rule_capacity(index, rule);
+                            entry.token_bucket(now, capacity, limit, period, cost)
+                        }
+                        Algorithm::Gcra => entry.gcra(now, limit, period, cost),
+                        Algorithm::LeakyBucket { max_wait } => {
+                            entry.leaky_bucket(now, slot, limit, period, max_wait, cost)
+                        }
+                    }
+                };
+
+                // a lone rule never records rejected hits; with several, hits are applied to
+                // copies and only kept when every rule allows them. Hits costing nothing are
+                // never kept.
+                if let ([entry], [rule], 1..) = (&mut *entries, rules, cost) {
+                    return vec![hit(entry, 0, rule)];
+                }
+
+                let mut updated = entries.to_vec();
+                let outcomes: Vec<_> = updated
+                    .iter_mut()
+                    .zip(rules)
+                    .enumerate()
+                    .map(|(index, (entry, rule))| hit(entry, index, rule))
+                    .collect();
+
+                let allowed = outcomes
+                    .iter()
+                    .zip(rules)
+                    .enumerate()
+                    .all(|(i, (outcome, rule))| outcome.0 <= algorithm.rule_capacity(i, rule));
+                if allowed && cost > 0 {
+                    entries.clone_from_slice(&updated);
+                }
+
+                outcomes
+            });
+
+            verdict(algorithm, rules, &outcomes, cost, None)
+        })
+    }
+}
+
+/// In-memory backend builder.
+#[derive(Debug)]
+pub struct MemoryBackendBuilder {
+    shards: usize,
+    max_keys: usize,
+    sweep_interval: Duration,
+}
+
+impl MemoryBackendBuilder {
+    /// Set number of independently locked shards. Defaults to 16.
+    pub fn shards(&mut self, shards: usize) -> &mut Self {
+        self.shards = shards.max(1);
+        self
+    }
+
+    /// Set upper bound on the number of tracked keys, counting each rule of a key separately.
+    /// Defaults to 100,000.
+    ///
+    /// When a shard is full, expired keys are evicted first, then the key closest to expiring.
+    pub fn max_keys(&mut self, max_keys: usize) -> &mut Self {
+        self.max_keys = max_keys.max(1);
+        self
+    }
+
+    /// Set interval between background sweeps of expired keys. Defaults to one minute.
+    pub fn sweep_interval(&mut self, interval: Duration) -> &mut Self {
+        self.sweep_interval = interval;
+        self
+    }
+
+    /// Finalizes and returns a `MemoryBackend`, starting its eviction thread.
+    ///
+    /// The thread exits on its next wake-up once every clone of the backend has been dropped.
+    pub fn build(&mut self) -> MemoryBackend {
+        let shards = Arc::new(Shards {
+            shards: (0..self.shards).map(|_| Mutex::default()).collect(),
+            capacity: self.max_keys.div_ceil(self.shards),
+            hasher: RandomState::new(),
+            origin: Instant::now(),
+        });
+
+        let weak = Arc::downgrade(&shards);
+        let interval = self.sweep_interval;
+        let spawned = thread::Builder::new()
+            .name("actix-limiter-sweeper".to_owned())
+            .spawn(move || sweep_until_dropped(weak, interval));
+
+        if let Err(err) = spawned {
+            log::warn!(
+                "Failed to start memory backend sweeper, relying on capacity eviction: {}",
+                err
+            );
+        }
+
+        MemoryBackend { shards }
+    }
+}
+
+fn sweep_until_dropped(shards: Weak<Shards>, interval: Duration) {
+    loop {
+        thread::sleep(interval);
+
+        match shards.upgrade() {
+            Some(shards) => shards.sweep(),
+            None => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FW: Algorithm = Algorithm::FixedWindow;
+
+    #[actix_web::test]
+    async fn test_fixed_window() {
+        let backend = MemoryBackend::new();
+        let period = Duration::from_secs(60);
+
+        let status = backend
+            .hit("key", FW, &[Rule::new(2, period)], 1)
+            .await
+            .unwrap();
+        assert_eq!(status.remaining(), 1);
+        let status = backend
+            .hit("key", FW, &[Rule::new(2, period)], 1)
+            .await
+            .unwrap();
+        assert_eq!(status.remaining(), 0);
+
+        match backend.hit("key", FW, &[Rule::new(2, period)], 1).await {
+            Err(Error::LimitExceeded(status)) => {
+                assert_eq!(status.limit(), 2);
+                assert_eq!(status.remaining(), 0);
+            }
+            res => panic!("expected limit to be exceeded, got {res:?}"),
+        }
+
+        assert!(
+            backend
+                .hit("other", FW, &[Rule::new(2, period)], 1)
+                .await
+                .is_ok()
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_window_expires() {
+        let backend = MemoryBackend::new();
+        let period = Duration::from_millis(50);
+
+        backend
+            .hit("key", FW, &[Rule::new(1, period)], 1)
+            .await
+            .unwrap();
+        assert!(
+            backend
+                .hit("key", FW, &[Rule::new(1, period)], 1)
+                .await
+                .is_err()
+        );
+
+        thread::sleep(Duration::from_millis(60));
+        assert!(
+            backend
+                .hit("key", FW, &[Rule::new(1, period)], 1)
+                .await
+                .is_ok()
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_max_keys() {
+        let backend = MemoryBackend::builder().shards(1).max_keys(2).build();
+        let rule = Rule::new(10, Duration::from_secs(60));
+
+        for key in ["a", "b", "c"] {
+            backend.hit(key, FW, &[rule], 1).await.unwrap();
+        }
+
+        let entries = backend.shards.shards[0].lock().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(!entries.contains_key(&rule_key("a", 0, &rule)));
+    }
+
+    #[actix_web::test]
+    async fn test_max_keys_evicts_expired_keys_only() {
+        let backend = MemoryBackend::builder().shards(1).max_keys(2).build();
+        let short = Rule::new(10, Duration::from_millis(1));
+        let long = Rule::new(10, Duration::from_secs(60));
+
+        backend.hit("expired", FW, &[short], 1).await.unwrap();
+        backend.hit("a", FW, &[long], 1).await.unwrap();
+        thread::sleep(Duration::from_millis(5));
+        backend.hit("b", FW, &[long], 1).await.unwrap();
+
+        let entries = backend.shards.shards[0].lock().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries.contains_key(&rule_key("a", 0, &long)));
+        assert!(entries.contains_key(&rule_key("b", 0, &long)));
+    }
+
+    #[actix_web::test]
+    async fn test_peek_does_not_evict() {
+        let backend = MemoryBackend::builder().shards(1).max_keys(1).build();
+        let rules = [Rule::new(2, Duration::from_secs(60))];
+
+        for _ in 0..2 {
+            backend.hit("a", FW, &rules, 1).await.unwrap();
+        }
+        let status = backend.hit("b", FW, &rules, 0).await.unwrap();
+        assert_eq!(status.remaining(), 2);
+
+        assert_eq!(backend.shards.shards[0].lock().unwrap().len(), 1);
+        assert!(backend.hit("a", FW, &rules, 1).await.is_err());
+    }
+
+    #[test]
+    fn test_sweep() {
+        let backend = MemoryBackend::builder()
+            .sweep_interval(Duration::from_millis(10))
+            .build();
+
+        for period in [Duration::from_millis(1), Duration::from_secs(60)] {
+            backend
+                .shards
+                .update("key", &[Rule::new(1, period)], |e, now| {
+                    e[0].fixed_window(now, 1, period, 1)
+                });
+        }
+        thread::sleep(Duration::from_millis(50));
+
+        let keys: usize = backend
+            .shards
+            .shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().len())
+            .sum();
+        assert_eq!(keys, 1);
+    }
+
+    #[actix_web::test]
+    async fn test_sliding_log() {
+        let backend = MemoryBackend::new();
+        let period = Duration::from_millis(100);
+        let log = Algorithm::SlidingLog;
+
+        backend
+            .hit("key", log, &[Rule::new(2, period)], 1)
+            .await
+            .unwrap();
+        thread::sleep(Duration::from_millis(50));
+        let status = backend
+            .hit("key", log, &[Rule::new(2, period)], 1)
+            .await
+            .unwrap();
+        assert_eq!(status.remaining(), 0);
+
+        // rejected hits are not recorded, so the first hit sliding out frees one slot
+        assert!(
+            backend
+                .hit("key", log, &[Rule::new(2, period)], 1)
+                .await
+                .is_err()
+        );
+        thread::sleep(Duration::from_millis(60));
+        backend
+            .hit("key", log, &[Rule::new(2, period)], 1)
+            .await
+            .unwrap();
+        assert!(
+            backend
+                .hit("key", log, &[Rule::new(2, period)], 1)
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        let origin = Instant::now();
+        let period = Duration::from_millis(200);
+        let mut entry = Entry {
+            state: State::Empty,
+            expires_at: origin,
+        };
+        let mut hit =
+            |at| entry.sliding_window(origin + Duration::from_millis(at), origin, 3, period, 1);
+
+        for count in [1, 2, 3] {
+            assert_eq!(hit(0).0, count);
+        }
+        assert_eq!(hit(0).0, 4);
+
+        // 20ms into the next window, the 3 previous hits still weigh 2.7, rounded up to 3; one
+        // more hit fits once their weight drops to 2, 67ms into the window
+        assert_eq!(hit(220), (4, Duration::from_millis(47)));
+
+        // at 120ms their weight of 1.2 leaves room for one hit, and the next waits until it drops
+        // to 1, 134ms into the window
+        assert_eq!(hit(320).0, 3);
+        assert_eq!(hit(320), (4, Duration::from_millis(14)));
+    }
+
+    #[test]
+    fn test_sliding_window_reset() {
+        let origin = Instant::now();
+        let period = Duration::from_millis(1000);
+        let mut entry = Entry {
+            state: State::Buckets {
+                window: 0,
+                current: 4,
+                previous: 0,
+            },
+            expires_at: origin,
+        };
+
+        // previous window held 4 hits; at 250ms in, 3 of them still count against a limit of 4
+        let (count, reset) =
+            entry.sliding_window(origin + Duration::from_millis(1250), origin, 4, period, 1);
+        assert_eq!(count, 4);
+        assert_eq!(reset, Duration::from_millis(750));
+
+        // limit reached; one more hit fits once the previous window's weight has halved
+        let (count, reset) =
+            entry.sliding_window(origin + Duration::from_millis(1250), origin, 4, period, 1);
+        assert_eq!(count, 5);
+        assert_eq!(reset, Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let start = Instant::now();
+        let mut entry = Entry {
+            state: State::Empty,
+            expires_at: start,
+        };
+        let interval = Duration::from_millis(50);
+        let mut hit = |at| entry.token_bucket(start + Duration::from_millis(at), 3, 1, interval, 1);
+
+        for count in [1, 2, 3] {
+            assert_eq!(hit(0).0, count);
+        }
+        assert_eq!(hit(0), (4, Duration::from_millis(50)));
+
+        // one token refilled after 50ms; the next one is only due at 100ms
+        assert_eq!(hit(60).0, 3);
+        assert_eq!(hit(60).0, 4);
+        assert_eq!(hit(99).0, 4);
+    }
+
+    #[test]
+    fn test_token_bucket_reset() {
+        let start = Instant::now();
+        let mut entry = Entry {
+            state: State::Empty,
+            expires_at: start,
+        };
+        let interval = Duration::from_secs(1);
+
+        // burst of 2, refilled at 4 tokens per second
+        let (count, full) = entry.token_bucket(start, 2, 4, interval, 1);
+        assert_eq!((count, full), (1, Duration::from_millis(250)));
+        let (count, full) = entry.token_bucket(start, 2, 4, interval, 1);
+        assert_eq!((count, full), (2, Duration::from_millis(500)));
+
+        let later = start + Duration::from_millis(100);
+        let (count, next) = entry.token_bucket(later, 2, 4, interval, 1);
+        assert_eq!((count, next), (3, Duration::from_millis(150)));
+    }
+
+    #[test]
+    fn test_gcra() {
+        let start = Instant::now();
+        let mut entry = Entry {
+            state: State::Empty,
+            expires_at: start,
+        };
+        // a hit every 100ms, bursts of 3
+        let period = Duration::from_millis(300);
+
+        for (count, tat) in [(1, 100), (2, 200), (3, 300)] {
+            assert_eq!(
+                entry.gcra(start, 3, period, 1),
+                (count, Duration::from_millis(tat))
+            );
+        }
+
+        let at = start + Duration::from_millis(30);
+        assert_eq!(entry.gcra(at, 3, period, 1), (4, Duration::from_millis(70)));
+
+        let at = start + Duration::from_millis(100);
+        assert_eq!(
+            entry.gcra(at, 3, period, 1),
+            (3, Duration::from_millis(300))
+        );
+    }
+
+    #[test]
+    fn test_leaky_bucket() {
+        let start = Instant::now();
+        let mut entry = Entry {
+            state: State::Empty,
+            expires_at: start,
+        };
+        // a slot every 100ms, waiting up to 250ms: queue of 3
+        let period = Duration::from_millis(300);
+        let max_wait = Duration::from_millis(250);
+
+        let mut hit = |now: Instant| {
+            let slot = entry.next_slot().map_or(now, |next| next.max(now));
+            entry.leaky_bucket(now, slot, 3, period, max_wait, 1)
+        };
+
+        for (count, drain) in [(1, 100), (2, 200), (3, 300)] {
+            assert_eq!(hit(start), (count, Duration::from_millis(drain)));
+        }
+        assert_eq!(hit(start), (4, Duration::from_millis(50)));
+
+        let at = start + Duration::from_millis(150);
+        assert_eq!(hit(at), (3, Duration::from_millis(250)));
+    }
+
+    #[actix_web::test]
+    async fn test_multiple_rules() {
+        let backend = MemoryBackend::new();
+        let rules = [
+            Rule::new(2, Duration::from_millis(100)),
+            Rule::new(3, Duration::from_secs(60)),
+        ];
+
+        for remaining in [1, 0] {
+            let status = backend.hit("key", FW, &rules, 1).await.unwrap();
+            assert_eq!(status.remaining(), remaining);
+            assert_eq!(status.period(), Some(rules[0].period()));
+        }
+        match backend.hit("key", FW, &rules, 1).await {
+            Err(Error::LimitExceeded(status)) => assert_eq!(status.limit(), 2),
+            res => panic!("expected limit to be exceeded, got {res:?}"),
+        }
+
+        // the rejected hit was not counted by the hourly rule either
+        thread::sleep(Duration::from_millis(110));
+        let status = backend.hit("key", FW, &rules, 1).await.unwrap();
+        assert_eq!(status.limit(), 3);
+        assert_eq!(status.remaining(), 0);
+
+        thread::sleep(Duration::from_millis(110));
+        match backend.hit("key", FW, &rules, 1).await {
+            Err(Error::LimitExceeded(status)) => {
+                assert_eq!(status.limit(), 3);
+                assert!(status.retry_after().unwrap() > Duration::from_secs(59));
+            }
+            res => panic!("expected limit to be exceeded, got {res:?}"),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_multiple_rules_token_bucket() {
+        let backend = MemoryBackend::new();
+        let tb = Algorithm::TokenBucket { capacity: 20 };
+        // bursts of 20 refilled at 5 per second, within a bucket of 10 per hour
+        let rules = [
+            Rule::new(5, Duration::from_secs(1)),
+            Rule::new(10, Duration::from_secs(3600)),
+        ];
+
+        let status = backend.hit("key", tb, &rules, 8).await.unwrap();
+        assert_eq!(status.limit(), 10);
+        assert_eq!(status.remaining(), 2);
+
+        match backend.hit("key", tb, &rules, 3).await {
+            Err(Error::LimitExceeded(status)) => {
+                assert_eq!(status.period(), Some(rules[1].period()));
+            }
+            res => panic!("expected limit to be exceeded, got {res:?}"),
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use std::{cmp::Reverse, fmt, future::Future, pin::Pin, time::Duration};
+
+use crate::{
+    algorithm::{Algorithm, emission_interval},
+    errors::Error,
+    rule::Rule,
+    status::Status,
+};
+
+mod memory;
+mod redis;
+
+pub use self::{
+    memory::{MemoryBackend, MemoryBackendBuilder},
+    redis::RedisBackend,
+};
+
+/// An owned, dynamically typed future returned by [`Backend`] methods.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Storage for rate limit counters.
+///
+/// Implementations are responsible for applying the limiting algorithm atomically per key.
+pub trait Backend: fmt::Debug + Send + Sync + 'static {
+    /// Consumes `cost` units for `key` from every rule using `algorithm`, only when all rules
+    /// allow it, and reports the most restrictive rule. A cost of 0 records nothing and only
+    /// reports the status.
+    ///
+    /// Returns [`Error::LimitExceeded`] carrying the status when the cost would take the key over
+    /// the limit of any rule.
+    fn hit<'a>(
+        &'a self,
+        key: &'a str,
+        algorithm: Algorithm,
+        rules: &'a [Rule],
+        cost: usize,
+    ) -> BoxFuture<'a, Result<Status, Error>>;
+}
+
+/// Builds the status of a hit from the `(count, reset_after)` outcome of each rule, where
+/// `count` units are used out of the algorithm's capacity.
+///
+/// Reports the most restrictive rule: the rejecting rule with the longest `reset_after`, used as
+/// the retry delay, or the rule with the fewest remaining units when none reject. Leaky bucket
+/// hits of `cost` units are delayed until their first slot, `cost` emission intervals before the
+/// queue drains at `reset_after`.
+///
+/// Resets are timed from `now`, the millisecond UNIX time the outcomes were measured at, or from
+/// the local clock when `None`.
+pub(crate) fn verdict(
+    algorithm: Algorithm,
+    rules: &[Rule],
+    outcomes: &[(usize, Duration)],
+    cost: usize,
+    now: Option<u64>,
+) -> Result<Status, Error> {
+    let (rule, count, reset_after, capacity) = rules
+        .iter()
+        .zip(outcomes)
+        .enumerate()
+        .map(|(index, (rule, &(count, reset_after)))| {
+            let capacity = algorithm.rule_capacity(index, rule);
+            (rule, count, reset_after, capacity)
+        })
+        .max_by_key(|&(_, count, reset_after, capacity)| {
+            let rejected = count > capacity;
+            (
+                rejected,
+                rejected.then_some(reset_after),
+                Reverse(capacity.saturating_sub(count)),
+                reset_after,
+            )
+        })
+        .ok_or_else(|| Error::Other("No rate limit rules".to_owned()))?;
+
+    let (reset_epoch_utc, reset_epoch_utc_ms) = match now {
+        Some(now) => Status::epoch_utc_at_plus(now, reset_after),
+        None => (
+            Status::epoch_utc_plus(reset_after)?,
+            Status::epoch_utc_ms_plus(reset_after)?,
+        ),
+    };
+    let status = Status::new(count, capacity, reset_epoch_utc)
+        .with_reset_epoch_utc_ms(reset_epoch_utc_ms)
+        .with_reset_after(reset_after)
+        .with_period(rule.period());
+
+    if count > capacity {
+        return Err(Error::LimitExceeded(status.with_retry_after(reset_after)));
+    }
+
+    match algorithm {
+        Algorithm::LeakyBucket { .. } => {
+            let interval = emission_interval(rule.limit(), rule.period());
+            let reserved = interval.saturating_mul(u32::try_from(cost).unwrap_or(u32::MAX));
+            Ok(status.with_delay(reset_after.saturating_sub(reserved)))
+        }
+        _ => Ok(status),
+    }
+}
+
+/// Returns the storage key of `rule` at `index`, identified by its position and period in
+/// milliseconds so that rule sets from [`limit_by`](crate::Builder::limit_by) never share a
+/// counter between different periods. The `{key}` hash tag keeps the rules of a key in the same
+/// Redis Cluster slot.
+pub(crate) fn rule_key(key: &str, index: usize, rule: &Rule) -> String {
+    format!("{{{key}}}:{index}:{}", rule.period().as_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeRedis;
+
+    async fn assert_cost(backend: impl Backend) {
+        let rules = [Rule::new(5, Duration::from_secs(60))];
+        let algorithms = [
+            Algorithm::FixedWindow,
+            Algorithm::SlidingLog,
+            Algorithm::SlidingWindow,
+            Algorithm::TokenBucket { capacity: 5 },
+            Algorithm::Gcra,
+        ];
+
+        for algorithm in algorithms {
+            let key = format!("{algorithm:?}");
+            let status = backend.hit(&key, algorithm, &rules, 3).await.unwrap();
+            assert_eq!(status.remaining(), 2, "{algorithm:?}");
+
+            // a cost that does not fit is rejected as a whole
+            assert!(matches!(
+                backend.hit(&key, algorithm, &rules, 3).await,
+                Err(Error::LimitExceeded(_))
+            ));
+
+            // a hit costing nothing only reports the status
+            let status = backend.hit(&key, algorithm, &rules, 0).await.unwrap();
+            assert_eq!(status.remaining(), 2, "{algorithm:?}");
+
+            let status = backend.hit(&key, algorithm, &rules, 2).await.unwrap();
+            assert_eq!(status.remaining(), 0, "{algorithm:?}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_cost() {
+        assert_cost(FakeRedis::start().backend()).await;
+        assert_cost(MemoryBackend::new()).await;
+    }
+
+    async fn assert_multiple_rules_leaky_bucket(backend: impl Backend) {
+        let leaky = Algorithm::LeakyBucket {
+            max_wait: Duration::from_millis(500),
+        };
+        // one slot every 50ms and every 100ms: the slower rule sets the pace
+        let rules = [
+            Rule::new(20, Duration::from_secs(1)),
+            Rule::new(10, Duration::from_secs(1)),
+        ];
+
+        backend.hit("key", leaky, &rules, 1).await.unwrap();
+        let status = backend.hit("key", leaky, &rules, 1).await.unwrap();
+        assert!(status.delay() > Duration::from_millis(90));
+    }
+
+    #[actix_web::test]
+    async fn test_multiple_rules_leaky_bucket() {
+        assert_multiple_rules_leaky_bucket(FakeRedis::start().backend()).await;
+        assert_multiple_rules_leaky_bucket(MemoryBackend::new()).await;
+    }
+
+    #[test]
+    fn test_verdict_reports_most_restrictive_rule() {
+        let second = Duration::from_secs(1);
+        let hour = Duration::from_secs(3600);
+        let rules = [Rule::new(10, second), Rule::new(1000, hour)];
+
+        let outcomes = [(2, second), (999, hour)];
+        let status = verdict(Algorithm::FixedWindow, &rules, &outcomes, 1, None).unwrap();
+        assert_eq!(status.limit(), 1000);
+        assert_eq!(status.remaining(), 1);
+        assert_eq!(status.period(), Some(hour));
+
+        let outcomes = [(11, second), (1001, hour)];
+        match verdict(Algorithm::FixedWindow, &rules, &outcomes, 1, None) {
+            Err(Error::LimitExceeded(status)) => {
+                assert_eq!(status.limit(), 1000);
+                assert_eq!(status.retry_after(), Some(hour));
+            }
+            res => panic!("expected limit to be exceeded, got {res:?}"),
+        }
+    }
+
+    #[test]
+    fn test_rule_key() {
+        let minute = Rule::new(10, Duration::from_secs(60));
+        assert_eq!(rule_key("ip:1", 0, &minute), "{ip:1}:0:60000");
+        assert_eq!(rule_key("ip:1", 2, &minute), "{ip:1}:2:60000");
+
+        let hour = Rule::new(10, Duration::from_secs(3600));
+        assert_ne!(rule_key("ip:1", 0, &minute), rule_key("ip:1", 0, &hour));
+    }
+}
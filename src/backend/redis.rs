@@ -0,0 +1,680 @@
+use std::{
+    hash::{BuildHasher as _, RandomState},
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, LazyLock, OnceLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use deadpool_redis::Pool;
+use redis::Script;
+
+use super::{Backend, BoxFuture, rule_key, verdict};
+use crate::{algorithm::Algorithm, errors::Error, rule::Rule, status::Status};
+
+/// Lua prelude resolving the current time in milliseconds: the time passed by the caller, or the
+/// Redis server's `TIME` when none was passed.
+const CLOCK: &str = r#"
+local function clock(arg)
+    local now = tonumber(arg)
+    if now then
+        return now
+    end
+
+    local time = redis.call("TIME")
+    return tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+end
+"#;
+
+// Every script takes one key per rule and a fixed-size group of arguments per rule, followed by
+// the shared arguments, starting with the cost of the hit. Rules are all evaluated before any is
+// updated, so that a hit is recorded by every rule or by none, and the script returns a
+// `{count, milliseconds}` pair per rule, followed by the time it ran at in milliseconds. A hit
+// costing 0 only reports the counts.
+
+/// Fixed window counters, per rule `limit, window`, then `cost, now`; returns the count and
+/// milliseconds until the window resets.
+static FIXED_WINDOW: LazyLock<Script> = LazyLock::new(|| Script::new(&[CLOCK, r#"
+local n    = #KEYS
+local cost = tonumber(ARGV[2 * n + 1])
+local now  = clock(ARGV[2 * n + 2])
+local res, allowed = {}, true
+
+for i = 1, n do
+    local limit = tonumber(ARGV[2 * i - 1])
+    local win   = tonumber(ARGV[2 * i])
+
+    local cnt = tonumber(redis.call("GET", KEYS[i]) or "0") + cost
+    local ttl = redis.call("PTTL", KEYS[i])
+    if ttl < 0 then ttl = win end
+
+    allowed = allowed and cnt <= limit
+    res[2 * i - 1], res[2 * i] = cnt, ttl
+end
+
+if allowed and cost > 0 then
+    for i = 1, n do
+        if redis.call("INCRBY", KEYS[i], cost) == cost then
+            redis.call("PEXPIRE", KEYS[i], res[2 * i])
+        end
+    end
+end
+
+res[2 * n + 1] = now
+return res
+"#].concat()));
+
+/// Sliding logs over sorted sets of hit timestamps, one member per unit of cost, per rule
+/// `limit, window`, then `cost, member, now`; returns the count (over the limit when rejected) and
+/// milliseconds until the oldest hit leaves the window, or until enough hits have left it for the
+/// cost when rejected.
+static SLIDING_LOG: LazyLock<Script> = LazyLock::new(|| Script::new(&[CLOCK, r#"
+local n    = #KEYS
+local cost = tonumber(ARGV[2 * n + 1])
+local id   = ARGV[2 * n + 2]
+local now  = clock(ARGV[2 * n + 3])
+local res, allowed = {}, true
+
+for i = 1, n do
+    local limit = tonumber(ARGV[2 * i - 1])
+    local win   = tonumber(ARGV[2 * i])
+
+    redis.call("ZREMRANGEBYSCORE", KEYS[i], "-inf", now - win)
+    local cnt = redis.call("ZCARD", KEYS[i]) + cost
+
+    local reset, rank = win, math.max(cnt - limit - 1, 0)
+    local oldest = redis.call("ZRANGE", KEYS[i], rank, rank, "WITHSCORES")
+    if oldest[2] then
+        reset = tonumber(oldest[2]) + win - now
+    end
+
+    allowed = allowed and cnt <= limit
+    res[2 * i - 1], res[2 * i] = cnt, reset
+end
+
+if allowed and cost > 0 then
+    for i = 1, n do
+        for j = 1, cost do
+            redis.call("ZADD", KEYS[i], now, id .. "-" .. j)
+        end
+        redis.call("PEXPIRE", KEYS[i], ARGV[2 * i])
+    end
+end
+
+res[2 * n + 1] = now
+return res
+"#].concat()));
+
+/// Sliding window counters stored as hashes of the current window and the current and previous
+/// window counts, per rule `limit, window`, then `cost, now`; returns the weighted count (over the
+/// limit when rejected) and milliseconds until the current window ends, or until the hit would be
+/// allowed when rejected.
+static SLIDING_WINDOW: LazyLock<Script> = LazyLock::new(|| Script::new(&[CLOCK, r#"
+local n    = #KEYS
+local cost = tonumber(ARGV[2 * n + 1])
+local now  = clock(ARGV[2 * n + 2])
+local res, state, allowed = {}, {}, true
+
+for i = 1, n do
+    local limit = tonumber(ARGV[2 * i - 1])
+    local win   = tonumber(ARGV[2 * i])
+
+    local window  = math.floor(now / win)
+    local elapsed = now - window * win
+
+    local stored = redis.call("HMGET", KEYS[i], "window", "cur", "prev")
+    local cur, prev = 0, 0
+    if tonumber(stored[1]) == window then
+        cur, prev = tonumber(stored[2]), tonumber(stored[3])
+    elseif tonumber(stored[1]) == window - 1 then
+        prev = tonumber(stored[2])
+    end
+
+    local cnt   = math.ceil(prev * (win - elapsed) / win + cur) + cost
+    local reset = win - elapsed
+
+    if cnt > limit and cost <= limit then
+        if cur + cost <= limit then
+            reset = math.ceil(win - (limit - cost - cur) * win / prev) - elapsed
+        else
+            reset = reset + math.ceil(win - (limit - cost) * win / cur)
+        end
+    end
+
+    allowed = allowed and cnt <= limit
+    state[i] = {window, cur, prev, 2 * win - elapsed}
+    res[2 * i - 1], res[2 * i] = cnt, reset
+end
+
+if allowed and cost > 0 then
+    for i = 1, n do
+        local window, cur, prev, ttl = unpack(state[i])
+        redis.call("HSET", KEYS[i], "window", window, "cur", cur + cost, "prev", prev)
+        redis.call("PEXPIRE", KEYS[i], ttl)
+    end
+end
+
+res[2 * n + 1] = now
+return res
+"#].concat()));
+
+/// Token buckets stored as hashes of the token count and last update time, per rule
+/// `capacity, refill, interval`, then `cost, now`; returns the count of used tokens (over the
+/// capacity when rejected) and milliseconds until the bucket is full again, or until enough
+/// tokens for the cost have arrived when rejected.
+static TOKEN_BUCKET: LazyLock<Script> = LazyLock::new(|| Script::new(&[CLOCK, r#"
+local n    = #KEYS
+local cost = tonumber(ARGV[3 * n + 1])
+local now  = clock(ARGV[3 * n + 2])
+local res, tokens, allowed = {}, {}, true
+
+for i = 1, n do
+    local capacity = tonumber(ARGV[3 * i - 2])
+    local rate     = tonumber(ARGV[3 * i - 1]) / tonumber(ARGV[3 * i])
+
+    local state = redis.call("HMGET", KEYS[i], "tokens", "ts")
+    local left  = tonumber(state[1]) or capacity
+    local ts    = tonumber(state[2]) or now
+
+    tokens[i] = math.min(capacity, left + math.max(0, now - ts) * rate)
+    allowed = allowed and tokens[i] >= cost
+end
+
+for i = 1, n do
+    local capacity = tonumber(ARGV[3 * i - 2])
+    local rate     = tonumber(ARGV[3 * i - 1]) / tonumber(ARGV[3 * i])
+
+    if tokens[i] < cost then
+        res[2 * i - 1], res[2 * i] = capacity + 1, math.ceil((cost - tokens[i]) / rate)
+    else
+        local left = tokens[i] - cost
+        res[2 * i - 1] = capacity - math.floor(left)
+        res[2 * i] = math.ceil((capacity - left) / rate)
+        if allowed then
+            tokens[i] = left
+        end
+    end
+
+    if cost > 0 then
+        local full = math.ceil((capacity - tokens[i]) / rate)
+        redis.call("HSET", KEYS[i], "tokens", tokens[i], "ts", now)
+        redis.call("PEXPIRE", KEYS[i], math.max(full, 1))
+    end
+end
+
+res[2 * n + 1] = now
+return res
+"#].concat()));
+
+/// GCRA over a single theoretical arrival time (TAT) per rule `limit, period`, then `cost, now`,
+/// advancing the TAT by one emission interval per unit of cost; returns the count of used burst
+/// capacity (over the limit when rejected) and milliseconds until the TAT, or until the hit would
+/// be allowed when rejected.
+static GCRA: LazyLock<Script> = LazyLock::new(|| Script::new(&[CLOCK, r#"
+local n    = #KEYS
+local cost = tonumber(ARGV[2 * n + 1])
+local now  = clock(ARGV[2 * n + 2])
+local res, tats, allowed = {}, {}, true
+
+for i = 1, n do
+    local limit  = tonumber(ARGV[2 * i - 1])
+    local period = tonumber(ARGV[2 * i])
+
+    if limit == 0 then
+        allowed = false
+        res[2 * i - 1], res[2 * i] = 1, period
+    else
+        local interval = period / limit
+        local tat = math.max(tonumber(redis.call("GET", KEYS[i])) or now, now)
+        local new_tat = tat + cost * interval
+        local allow_at = new_tat - period
+
+        if now < allow_at then
+            allowed = false
+            res[2 * i - 1], res[2 * i] = limit + 1, math.ceil(allow_at - now)
+        else
+            local remaining = math.floor((period - (new_tat - now)) / interval + 1e-9)
+            res[2 * i - 1], res[2 * i] = limit - remaining, math.ceil(new_tat - now)
+            tats[i] = new_tat
+        end
+    end
+end
+
+if allowed and cost > 0 then
+    for i = 1, n do
+        redis.call("SET", KEYS[i], tats[i], "PX", res[2 * i])
+    end
+end
+
+res[2 * n + 1] = now
+return res
+"#].concat()));
+
+/// Leaky bucket queues over the next free slot, per rule `capacity, limit, period`, then
+/// `cost, max_wait, now`; all rules share the latest of their slots, and a hit takes one slot per
+/// unit of cost. Returns the number of queued hits (over the capacity when rejected) and
+/// milliseconds until the queue drains, or until the hit would fit within the maximum wait when
+/// rejected.
+static LEAKY_BUCKET: LazyLock<Script> = LazyLock::new(|| Script::new(&[CLOCK, r#"
+local n        = #KEYS
+local cost     = tonumber(ARGV[3 * n + 1])
+local max_wait = tonumber(ARGV[3 * n + 2])
+local now      = clock(ARGV[3 * n + 3])
+local res      = {}
+
+local slot = now
+for i = 1, n do
+    slot = math.max(slot, tonumber(redis.call("GET", KEYS[i])) or now)
+end
+local delay = slot - now
+local allowed = delay <= max_wait
+
+for i = 1, n do
+    local capacity = tonumber(ARGV[3 * i - 2])
+    local limit    = tonumber(ARGV[3 * i - 1])
+    local period   = tonumber(ARGV[3 * i])
+
+    if limit == 0 then
+        allowed = false
+        res[2 * i - 1], res[2 * i] = 1, period
+    elseif delay > max_wait then
+        res[2 * i - 1], res[2 * i] = capacity + 1, math.ceil(delay - max_wait)
+    else
+        local interval = period / limit
+        local queued = math.ceil(delay / interval - 1e-9) + cost
+        res[2 * i - 1], res[2 * i] = math.min(queued, capacity), math.ceil(delay + cost * interval)
+    end
+end
+
+if allowed and cost > 0 then
+    for i = 1, n do
+        local interval = tonumber(ARGV[3 * i]) / tonumber(ARGV[3 * i - 1])
+        redis.call("SET", KEYS[i], slot + cost * interval, "PX", res[2 * i])
+    end
+end
+
+res[2 * n + 1] = now
+return res
+"#].concat()));
+
+/// Redis backend running the limiting algorithm as a single Lua script.
+///
+/// Scripts are invoked by their SHA1 digest with `EVALSHA`, and loaded again whenever Redis reports
+/// them missing, e.g. after a restart or failover.
+#[derive(Debug, Clone)]
+pub struct RedisBackend {
+    pool: Arc<Pool>,
+    server_time: bool,
+}
+
+impl RedisBackend {
+    /// Constructs a Redis backend from a connection pool.
+    ///
+    /// See [`redis-rs` docs](https://docs.rs/redis/0.21/redis/#connection-parameters) on connection
+    /// parameters for how to set the Redis URL.
+    #[must_use]
+    pub fn new(pool: Arc<Pool>) -> Self {
+        RedisBackend {
+            pool,
+            server_time: false,
+        }
+    }
+
+    /// Reads the current time from the Redis server (`TIME`) inside the scripts instead of
+    /// passing each application node's clock, so that nodes with skewed clocks agree on windows
+    /// and resets. Reset timestamps of the returned statuses are based on the server time too.
+    ///
+    /// Requires Redis 5 or later, which replicates script effects rather than the scripts.
+    #[must_use]
+    pub fn with_server_time(mut self) -> Self {
+        self.server_time = true;
+        self
+    }
+
+    /// Returns the current time in milliseconds to pass to the scripts, or `None` to let them read
+    /// the server time.
+    fn now(&self) -> Option<u64> {
+        (!self.server_time).then(now_millis)
+    }
+
+    /// Runs the script of `algorithm` at `now` milliseconds, or at the server time when `None`.
+    async fn hit_at(
+        &self,
+        key: &str,
+        algorithm: Algorithm,
+        rules: &[Rule],
+        cost: usize,
+        now: Option<u64>,
+    ) -> Result<Status, Error> {
+        let mut conn = self.pool.get().await?;
+
+        let script = match algorithm {
+            Algorithm::FixedWindow => &FIXED_WINDOW,
+            Algorithm::SlidingLog => &SLIDING_LOG,
+            Algorithm::SlidingWindow => &SLIDING_WINDOW,
+            Algorithm::TokenBucket { .. } => &TOKEN_BUCKET,
+            Algorithm::Gcra => &GCRA,
+            Algorithm::LeakyBucket { .. } => &LEAKY_BUCKET,
+        };
+        let mut invocation = script.prepare_invoke();
+
+        for (index, rule) in rules.iter().enumerate() {
+            let capacity = algorithm.rule_capacity(index, rule);
+            let period = (rule.period().as_millis() as u64).max(1);
+
+            invocation.key(rule_key(key, index, rule));
+            match algorithm {
+                Algorithm::TokenBucket { .. } | Algorithm::LeakyBucket { .. } => {
+                    invocation
+                        .arg(capacity)     // capacity
+                        .arg(rule.limit()) // refill or limit
+                        .arg(period);      // interval or period
+                }
+                _ => {
+                    invocation
+                        .arg(rule.limit()) // limit
+                        .arg(period);      // window or period
+                }
+            }
+        }
+
+        invocation.arg(cost);
+        match algorithm {
+            Algorithm::SlidingLog => {
+                invocation.arg(log_member()).arg(now);
+            }
+            Algorithm::LeakyBucket { max_wait } => {
+                invocation.arg(max_wait.as_millis() as u64).arg(now);
+            }
+            _ => {
+                invocation.arg(now);
+            }
+        }
+
+        let mut res: Vec<u64> = invocation.invoke_async(&mut *conn).await?;
+        // resets are relative to the time the script ran at, the server time unless `now` is set
+        let ran_at = res.pop();
+        let outcomes: Vec<_> = res
+            .chunks(2)
+            .map(|pair| (pair[0] as usize, Duration::from_millis(pair[1])))
+            .collect();
+
+        verdict(algorithm, rules, &outcomes, cost, ran_at)
+    }
+}
+
+impl Backend for RedisBackend {
+    fn hit<'a>(
+        &'a self,
+        key: &'a str,
+        algorithm: Algorithm,
+        rules: &'a [Rule],
+        cost: usize,
+    ) -> BoxFuture<'a, Result<Status, Error>> {
+        Box::pin(self.hit_at(key, algorithm, rules, cost, self.now()))
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Returns a sorted set member for a hit, unique across processes so that concurrent hits in the
+/// same millisecond are all recorded.
+fn log_member() -> String {
+    static SEQ: AtomicU64 = AtomicU64::new(0);
+    static NODE: OnceLock<u64> = OnceLock::new();
+
+    let node = NODE.get_or_init(|| RandomState::new().hash_one(process::id()));
+    let seq = SEQ.fetch_add(1, Ordering::Relaxed);
+
+    format!("{node:x}-{seq:x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::testing::FakeRedis;
+
+    #[actix_web::test]
+    async fn test_fixed_window() {
+        let backend = FakeRedis::start().backend();
+        let fw = Algorithm::FixedWindow;
+        let period = Duration::from_secs(60);
+
+        let status = backend.hit("key", fw, &[Rule::new(2, period)], 1).await.unwrap();
+        assert_eq!(status.remaining(), 1);
+        backend.hit("key", fw, &[Rule::new(2, period)], 1).await.unwrap();
+        assert!(matches!(
+            backend.hit("key", fw, &[Rule::new(2, period)], 1).await,
+            Err(Error::LimitExceeded(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_sub_second_fixed_window() {
+        let backend = FakeRedis::start().backend();
+        let fw = Algorithm::FixedWindow;
+        let period = Duration::from_millis(100);
+
+        let before = now_millis();
+        let status = backend.hit("key", fw, &[Rule::new(1, period)], 1).await.unwrap();
+        assert!(status.reset_epoch_utc_ms() <= before + 150);
+        assert!(backend.hit("key", fw, &[Rule::new(1, period)], 1).await.is_err());
+
+        thread::sleep(Duration::from_millis(120));
+        assert!(backend.hit("key", fw, &[Rule::new(1, period)], 1).await.is_ok());
+    }
+
+    #[actix_web::test]
+    async fn test_multiple_rules() {
+        let backend = FakeRedis::start().backend();
+        let rules = [
+            Rule::new(2, Duration::from_millis(100)),
+            Rule::new(3, Duration::from_secs(60)),
+        ];
+
+        for algorithm in [
+            Algorithm::FixedWindow,
+            Algorithm::SlidingLog,
+            Algorithm::SlidingWindow,
+            Algorithm::TokenBucket { capacity: 2 },
+            Algorithm::Gcra,
+        ] {
+            let key = format!("{algorithm:?}");
+            for _ in 0..2 {
+                backend.hit(&key, algorithm, &rules, 1).await.unwrap();
+            }
+            match backend.hit(&key, algorithm, &rules, 1).await {
+                Err(Error::LimitExceeded(status)) => {
+                    assert_eq!(status.period(), Some(rules[0].period()), "{key}");
+                }
+                res => panic!("expected limit to be exceeded, got {res:?}"),
+            }
+
+            // the rejected hit was not counted by the second rule either
+            thread::sleep(Duration::from_millis(220));
+            let status = backend.hit(&key, algorithm, &rules, 1).await.unwrap();
+            assert_eq!(status.period(), Some(rules[1].period()), "{key}");
+            assert_eq!(status.remaining(), 0, "{key}");
+            assert!(backend.hit(&key, algorithm, &rules, 1).await.is_err(), "{key}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_server_time() {
+        let redis = FakeRedis::start();
+        // the server clock runs an hour ahead of the application's
+        redis.skew_clock(3_600_000);
+        let backend = redis.backend().with_server_time();
+        assert_eq!(backend.now(), None);
+        let period = Duration::from_secs(60);
+
+        for algorithm in [
+            Algorithm::FixedWindow,
+            Algorithm::SlidingLog,
+            Algorithm::SlidingWindow,
+            Algorithm::Gcra,
+        ] {
+            let key = format!("{algorithm:?}");
+            let rules = [Rule::new(2, period)];
+            for remaining in [1, 0] {
+                let status = backend.hit(&key, algorithm, &rules, 1).await.unwrap();
+                assert_eq!(status.remaining(), remaining);
+                // resets are timed from the server clock
+                assert!(status.reset_epoch_utc_ms() > now_millis() + 3_600_000, "{key}");
+                assert!(status.reset_epoch_utc() as u64 > now_millis() / 1000 + 3_600, "{key}");
+            }
+            assert!(backend.hit(&key, algorithm, &rules, 1).await.is_err());
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_reloads_script_after_flush() {
+        let redis = FakeRedis::start();
+        let backend = redis.backend();
+        let rules = [Rule::new(5, Duration::from_secs(60))];
+
+        for _ in 0..3 {
+            backend.hit("key", Algorithm::FixedWindow, &rules, 1).await.unwrap();
+        }
+        assert_eq!(redis.scripts_loaded(), 1);
+
+        redis.flush_scripts();
+        let status = backend.hit("key", Algorithm::FixedWindow, &rules, 1).await.unwrap();
+        assert_eq!(status.remaining(), 1);
+        assert_eq!(redis.scripts_loaded(), 2);
+    }
+
+    #[actix_web::test]
+    async fn test_sliding_log() {
+        let backend = FakeRedis::start().backend();
+        let log = Algorithm::SlidingLog;
+        let period = Duration::from_millis(200);
+
+        backend.hit("key", log, &[Rule::new(2, period)], 1).await.unwrap();
+        thread::sleep(Duration::from_millis(100));
+        let status = backend.hit("key", log, &[Rule::new(2, period)], 1).await.unwrap();
+        assert_eq!(status.remaining(), 0);
+
+        match backend.hit("key", log, &[Rule::new(2, period)], 1).await {
+            Err(Error::LimitExceeded(status)) => assert_eq!(status.remaining(), 0),
+            res => panic!("expected limit to be exceeded, got {res:?}"),
+        }
+
+        // only the first hit has slid out of the window
+        thread::sleep(Duration::from_millis(120));
+        backend.hit("key", log, &[Rule::new(2, period)], 1).await.unwrap();
+        assert!(backend.hit("key", log, &[Rule::new(2, period)], 1).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_sliding_window() {
+        let backend = FakeRedis::start().backend();
+        let sw = Algorithm::SlidingWindow;
+        let rules = [Rule::new(3, Duration::from_millis(200))];
+        // milliseconds into the window starting at 1,000,000
+        let hit = |at: u64| backend.hit_at("key", sw, &rules, 1, Some(1_000_000 + at));
+
+        for remaining in [2, 1, 0] {
+            assert_eq!(hit(0).await.unwrap().remaining(), remaining);
+        }
+        assert!(hit(0).await.is_err());
+
+        // early in the next window nearly all of the previous count still applies, until a third
+        // of it has slid out
+        match hit(220).await {
+            Err(Error::LimitExceeded(status)) => {
+                assert_eq!(status.retry_after(), Some(Duration::from_millis(47)));
+            }
+            res => panic!("expected limit to be exceeded, got {res:?}"),
+        }
+
+        // past the middle of it, the weighted count has dropped enough for one hit
+        hit(320).await.unwrap();
+        assert!(hit(320).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_token_bucket() {
+        let backend = FakeRedis::start().backend();
+        let tb = Algorithm::TokenBucket { capacity: 3 };
+        // one token every 50ms
+        let rules = [Rule::new(1, Duration::from_millis(50))];
+        let hit = |at: u64| backend.hit_at("key", tb, &rules, 1, Some(1_000_000 + at));
+
+        for remaining in [2, 1, 0] {
+            let status = hit(0).await.unwrap();
+            assert_eq!(status.limit(), 3);
+            assert_eq!(status.remaining(), remaining);
+        }
+        match hit(20).await {
+            Err(Error::LimitExceeded(status)) => {
+                assert_eq!(status.retry_after(), Some(Duration::from_millis(30)));
+            }
+            res => panic!("expected limit to be exceeded, got {res:?}"),
+        }
+
+        hit(50).await.unwrap();
+        assert!(hit(50).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_gcra() {
+        let backend = FakeRedis::start().backend();
+        // a hit every 50ms, bursts of 3
+        let rules = [Rule::new(3, Duration::from_millis(150))];
+        let hit = |at: u64| backend.hit_at("key", Algorithm::Gcra, &rules, 1, Some(1_000_000 + at));
+
+        for remaining in [2, 1, 0] {
+            let status = hit(0).await.unwrap();
+            assert_eq!(status.remaining(), remaining);
+            assert_eq!(status.retry_after(), None);
+        }
+
+        match hit(20).await {
+            Err(Error::LimitExceeded(status)) => {
+                assert_eq!(status.retry_after(), Some(Duration::from_millis(30)));
+            }
+            res => panic!("expected limit to be exceeded, got {res:?}"),
+        }
+
+        hit(50).await.unwrap();
+        assert!(hit(50).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_leaky_bucket() {
+        let backend = FakeRedis::start().backend();
+        let leaky = Algorithm::LeakyBucket {
+            max_wait: Duration::from_millis(250),
+        };
+        // a slot every 100ms, waiting up to 250ms: queue of 3
+        let period = Duration::from_millis(300);
+
+        let status = backend.hit("key", leaky, &[Rule::new(3, period)], 1).await.unwrap();
+        assert_eq!(status.limit(), 3);
+        assert_eq!(status.delay(), Duration::ZERO);
+
+        for remaining in [1, 0] {
+            let status = backend.hit("key", leaky, &[Rule::new(3, period)], 1).await.unwrap();
+            assert_eq!(status.remaining(), remaining);
+            assert!(status.delay() > Duration::from_millis(50));
+        }
+
+        match backend.hit("key", leaky, &[Rule::new(3, period)], 1).await {
+            Err(Error::LimitExceeded(status)) => {
+                let retry_after = status.retry_after().unwrap();
+                assert!(retry_after > Duration::ZERO && retry_after <= Duration::from_millis(50));
+            }
+            res => panic!("expected limit to be exceeded, got {res:?}"),
+        }
+    }
+}
@@ -1,19 +1,40 @@
-use std::{borrow::Cow, sync::Arc, time::Duration};
+use std::{borrow::Cow, collections::HashSet, sync::Arc, time::Duration};
 
 #[cfg(feature = "session")]
 use actix_session::SessionExt as _;
-use actix_web::dev::ServiceRequest;
-use deadpool_redis::Pool;
+use actix_web::{dev::ServiceRequest, HttpResponse};
 
-use crate::{errors::Error, GetArcBoxKeyFn, Limiter};
+use crate::{
+    circuit::CircuitBreaker, errors::Error, keys::IpNet, Algorithm, Backend, FailurePolicy,
+    GetArcAsyncKeyFn, GetArcBoxKeyFn, GetArcCostFn, GetArcFilterFn, GetArcLimitExceededFn,
+    GetArcRulesFn, HeaderFormat, Limiter, LocalBoxFuture, MemoryBackend, Rule, Status,
+};
 
 /// Rate limiter builder.
 #[derive(Debug)]
 pub struct Builder {
-    pub(crate) redis: Arc<Pool>,
+    pub(crate) backend: Arc<dyn Backend>,
     pub(crate) limit: usize,
     pub(crate) period: Duration,
+    pub(crate) rules: Vec<Rule>,
+    pub(crate) algorithm: Algorithm,
     pub(crate) get_key_fn: Option<GetArcBoxKeyFn>,
+    pub(crate) get_async_key_fn: Option<GetArcAsyncKeyFn>,
+    pub(crate) get_rules_fn: Option<GetArcRulesFn>,
+    pub(crate) get_cost_fn: Option<GetArcCostFn>,
+    pub(crate) allow: Vec<GetArcFilterFn>,
+    pub(crate) deny: Vec<GetArcFilterFn>,
+    pub(crate) allow_networks: Vec<IpNet>,
+    pub(crate) deny_networks: Vec<IpNet>,
+    pub(crate) get_client_ip_fn: Option<GetArcBoxKeyFn>,
+    pub(crate) header_format: HeaderFormat,
+    pub(crate) limit_exceeded_fn: Option<GetArcLimitExceededFn>,
+    pub(crate) failure_policy: FailurePolicy,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) circuit_breaker: Option<(u32, Duration)>,
+    pub(crate) key_prefix: Option<Cow<'static, str>>,
+    pub(crate) name: Option<Cow<'static, str>>,
+    pub(crate) hash_keys_over: Option<usize>,
     pub(crate) cookie_name: Cow<'static, str>,
     #[cfg(feature = "session")]
     pub(crate) session_key: Cow<'static, str>,
@@ -32,6 +53,83 @@ impl Builder {
         self
     }
 
+    /// Adds a quota of `limit` requests per `period` that requests must satisfy in addition to
+    /// [`limit`](Self::limit) per [`period`](Self::period).
+    ///
+    /// All rules are checked and updated atomically, and rejected requests consume nothing from
+    /// any rule. For example, 10 requests per second and at most 1000 per hour:
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use actix_limiter::{Limiter, MemoryBackend};
+    /// let limiter = Limiter::builder(MemoryBackend::new())
+    ///     .limit(10)
+    ///     .period(Duration::from_secs(1))
+    ///     .rule(1000, Duration::from_secs(3600))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn rule(&mut self, limit: usize, period: Duration) -> &mut Self {
+        self.rules.push(Rule::new(limit, period));
+        self
+    }
+
+    /// Set limiting algorithm.
+    ///
+    /// Defaults to [`Algorithm::FixedWindow`].
+    pub fn algorithm(&mut self, algorithm: Algorithm) -> &mut Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Use a token bucket holding up to `capacity` tokens, refilled with `refill` tokens every
+    /// `interval`. [`build`](Self::build) fails when `refill` or `interval` is zero.
+    ///
+    /// Rules added with [`rule`](Self::rule) are buckets of their own `limit` tokens, refilled
+    /// over their `period`.
+    ///
+    /// For example, a burst of 20 followed by 5 requests per second:
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use actix_limiter::{Limiter, MemoryBackend};
+    /// let limiter = Limiter::builder(MemoryBackend::new())
+    ///     .token_bucket(20, 5, Duration::from_secs(1))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn token_bucket(
+        &mut self,
+        capacity: usize,
+        refill: usize,
+        interval: Duration,
+    ) -> &mut Self {
+        self.algorithm = Algorithm::TokenBucket { capacity };
+        self.limit = refill;
+        self.period = interval;
+        self
+    }
+
+    /// Use a leaky bucket queue: requests are spaced evenly at `limit` per `period` and delayed
+    /// until their slot, only being rejected when they would wait longer than `max_wait`.
+    ///
+    /// For example, 10 requests per second queued for up to half a second:
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use actix_limiter::{Limiter, MemoryBackend};
+    /// let limiter = Limiter::builder(MemoryBackend::new())
+    ///     .limit(10)
+    ///     .period(Duration::from_secs(1))
+    ///     .leaky_bucket(Duration::from_millis(500))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn leaky_bucket(&mut self, max_wait: Duration) -> &mut Self {
+        self.algorithm = Algorithm::LeakyBucket { max_wait };
+        self
+    }
+
     /// Sets rate limit key derivation function.
     ///
     /// Should not be used in combination with `cookie_name` or `session_key` as they conflict.
@@ -40,6 +138,309 @@ impl Builder {
         F: Fn(&ServiceRequest) -> Option<String> + Send + Sync + 'static,
     {
         self.get_key_fn = Some(Arc::new(resolver));
+        self.get_async_key_fn = None;
+        self
+    }
+
+    /// Sets an asynchronous rate limit key derivation function, awaited by the middleware before
+    /// counting, e.g. to resolve an API key to a tenant through a cache or database.
+    ///
+    /// Replaces any resolver set with [`key_by`](Self::key_by).
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use actix_limiter::{Limiter, MemoryBackend};
+    /// # async fn tenant_of(api_key: &str) -> Option<String> { Some(api_key.to_owned()) }
+    /// let limiter = Limiter::builder(MemoryBackend::new())
+    ///     .key_by_async(|req| {
+    ///         Box::pin(async move {
+    ///             let api_key = req.headers().get("x-api-key")?.to_str().ok()?;
+    ///             tenant_of(api_key).await
+    ///         })
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn key_by_async<F>(&mut self, resolver: F) -> &mut Self
+    where
+        F: for<'a> Fn(&'a ServiceRequest) -> LocalBoxFuture<'a, Option<String>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.get_async_key_fn = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Sets a resolver of the rules applying to each request, e.g. from the customer's plan.
+    ///
+    /// The resolved rules replace the builder's [`limit`](Self::limit), [`period`](Self::period)
+    /// and [`rule`](Self::rule)s for that request; returning `None` keeps them. Rules are passed to
+    /// the backend on every call, so quotas can change without a restart. Each rule counts under
+    /// its index and period, so a key moving to another plan only keeps the usage of rules whose
+    /// index and period match, and starts afresh on the others.
+    ///
+    /// Resolved rules failing the checks of [`build`](Self::build), or an empty list, are logged
+    /// and replaced by the builder's rules for that request.
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use actix_limiter::{Limiter, MemoryBackend, Rule};
+    /// let limiter = Limiter::builder(MemoryBackend::new())
+    ///     .limit(100)
+    ///     .period(Duration::from_secs(3600))
+    ///     .limit_by(|req| match req.headers().get("x-plan")?.to_str().ok()? {
+    ///         "pro" => Some(vec![Rule::new(10_000, Duration::from_secs(3600))]),
+    ///         _ => None,
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn limit_by<F>(&mut self, resolver: F) -> &mut Self
+    where
+        F: Fn(&ServiceRequest) -> Option<Vec<Rule>> + Send + Sync + 'static,
+    {
+        self.get_rules_fn = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Sets a resolver of the number of units each request consumes, e.g. by endpoint or batch
+    /// size. Requests cost 1 unit by default.
+    ///
+    /// ```
+    /// # use actix_limiter::{Limiter, MemoryBackend};
+    /// let limiter = Limiter::builder(MemoryBackend::new())
+    ///     .limit(1000)
+    ///     .cost_by(|req| match req.path() {
+    ///         "/graphql" => 10,
+    ///         "/bulk" => 50,
+    ///         _ => 1,
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn cost_by<F>(&mut self, resolver: F) -> &mut Self
+    where
+        F: Fn(&ServiceRequest) -> usize + Send + Sync + 'static,
+    {
+        self.get_cost_fn = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Exempts requests from limiting when `predicate` holds for the request and its key, e.g.
+    /// internal health checks. Exempt requests are not counted and get no rate limit headers.
+    ///
+    /// Denylist entries take precedence over allowlist entries.
+    ///
+    /// ```
+    /// # use actix_limiter::{keys, Limiter, MemoryBackend};
+    /// let limiter = Limiter::builder(MemoryBackend::new())
+    ///     .key_by(keys::peer_ip())
+    ///     .allow_if(|req, _key| req.path() == "/health")
+    ///     .allow_networks(["192.0.2.0/24".parse().unwrap()])
+    ///     .deny_keys(["203.0.113.7"])
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn allow_if<F>(&mut self, predicate: F) -> &mut Self
+    where
+        F: Fn(&ServiceRequest, &str) -> bool + Send + Sync + 'static,
+    {
+        self.allow.push(Arc::new(predicate));
+        self
+    }
+
+    /// Exempts requests whose key is one of `keys` from limiting. See
+    /// [`allow_if`](Self::allow_if).
+    pub fn allow_keys<I>(&mut self, keys: I) -> &mut Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let keys: HashSet<String> = keys.into_iter().map(Into::into).collect();
+        self.allow_if(move |_, key| keys.contains(key))
+    }
+
+    /// Exempts requests whose client address is within one of `networks` from limiting,
+    /// whatever their key. See [`client_ip_by`](Self::client_ip_by).
+    pub fn allow_networks(&mut self, networks: impl IntoIterator<Item = IpNet>) -> &mut Self {
+        self.allow_networks.extend(networks);
+        self
+    }
+
+    /// Rejects requests with `403 Forbidden` when `predicate` holds for the request and its key,
+    /// without counting them, e.g. for abusive clients.
+    pub fn deny_if<F>(&mut self, predicate: F) -> &mut Self
+    where
+        F: Fn(&ServiceRequest, &str) -> bool + Send + Sync + 'static,
+    {
+        self.deny.push(Arc::new(predicate));
+        self
+    }
+
+    /// Rejects requests whose key is one of `keys`. See [`deny_if`](Self::deny_if).
+    pub fn deny_keys<I>(&mut self, keys: I) -> &mut Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let keys: HashSet<String> = keys.into_iter().map(Into::into).collect();
+        self.deny_if(move |_, key| keys.contains(key))
+    }
+
+    /// Rejects requests whose client address is within one of `networks`, before their key is
+    /// resolved, so they are rejected even when they have no key. See
+    /// [`client_ip_by`](Self::client_ip_by).
+    pub fn deny_networks(&mut self, networks: impl IntoIterator<Item = IpNet>) -> &mut Self {
+        self.deny_networks.extend(networks);
+        self
+    }
+
+    /// Sets how the client address matched by [`allow_networks`](Self::allow_networks) and
+    /// [`deny_networks`](Self::deny_networks) is derived. Defaults to the peer address; behind a
+    /// reverse proxy use [`keys::real_ip`](crate::keys::real_ip).
+    ///
+    /// ```
+    /// # use actix_limiter::{keys::{self, ForwardedHeader, IpNet}, Limiter, MemoryBackend};
+    /// let trusted_proxies: [IpNet; 1] = ["10.0.0.0/8".parse().unwrap()];
+    /// let limiter = Limiter::builder(MemoryBackend::new())
+    ///     .key_by(keys::header("x-api-key"))
+    ///     .client_ip_by(keys::real_ip(ForwardedHeader::XForwardedFor, trusted_proxies))
+    ///     .deny_networks(["198.51.100.0/24".parse().unwrap()])
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn client_ip_by<F>(&mut self, resolver: F) -> &mut Self
+    where
+        F: Fn(&ServiceRequest) -> Option<String> + Send + Sync + 'static,
+    {
+        self.get_client_ip_fn = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Sets which rate limit header fields are attached to responses.
+    ///
+    /// Defaults to [`HeaderFormat::Legacy`].
+    pub fn header_format(&mut self, format: HeaderFormat) -> &mut Self {
+        self.header_format = format;
+        self
+    }
+
+    /// Sets the response to requests over the limit, built from the request and the status of the
+    /// most restrictive rule. Rate limit headers are added to the response afterwards.
+    ///
+    /// Defaults to `429 Too Many Requests` with an
+    /// [RFC 9457](https://www.rfc-editor.org/rfc/rfc9457) `application/problem+json` body carrying
+    /// `limit`, `remaining` and `retry_after` (seconds).
+    ///
+    /// ```
+    /// # use actix_limiter::{Limiter, MemoryBackend};
+    /// # use actix_web::HttpResponse;
+    /// let limiter = Limiter::builder(MemoryBackend::new())
+    ///     .on_limit_exceeded(|_req, status| {
+    ///         HttpResponse::TooManyRequests()
+    ///             .body(format!("Slow down, {} requests allowed", status.limit()))
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn on_limit_exceeded<F>(&mut self, respond: F) -> &mut Self
+    where
+        F: Fn(&ServiceRequest, &Status) -> HttpResponse + Send + Sync + 'static,
+    {
+        self.limit_exceeded_fn = Some(Arc::new(respond));
+        self
+    }
+
+    /// Sets how requests are handled when the backend fails, e.g. while Redis is unreachable.
+    ///
+    /// Defaults to [`FailurePolicy::FailClosed`].
+    ///
+    /// ```
+    /// # use std::sync::Arc;
+    /// # use actix_limiter::{FailurePolicy, Limiter, RedisBackend};
+    /// # let pool = deadpool_redis::Config::from_url("redis://127.0.0.1:6379")
+    /// #     .create_pool(Some(deadpool_redis::Runtime::Tokio1))
+    /// #     .unwrap();
+    /// let limiter = Limiter::builder(RedisBackend::new(Arc::new(pool)))
+    ///     .failure_policy(FailurePolicy::Fallback)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn failure_policy(&mut self, policy: FailurePolicy) -> &mut Self {
+        self.failure_policy = policy;
+        self
+    }
+
+    /// Sets how long a backend call may take before failing with
+    /// [`Error::Timeout`](crate::Error::Timeout), e.g. while waiting for a Redis connection.
+    ///
+    /// There is no timeout by default.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Stops calling the backend after `failure_threshold` consecutive failures or timeouts,
+    /// applying the [failure policy](Self::failure_policy) right away instead, and lets a single
+    /// request probe the backend every `reset_timeout` until it recovers.
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use actix_limiter::{FailurePolicy, Limiter, MemoryBackend};
+    /// let limiter = Limiter::builder(MemoryBackend::new())
+    ///     .timeout(Duration::from_millis(50))
+    ///     .circuit_breaker(5, Duration::from_secs(10))
+    ///     .failure_policy(FailurePolicy::FailOpen)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn circuit_breaker(
+        &mut self,
+        failure_threshold: u32,
+        reset_timeout: Duration,
+    ) -> &mut Self {
+        self.circuit_breaker = Some((failure_threshold, reset_timeout));
+        self
+    }
+
+    /// Prefixes the backend keys of this limiter with `prefix`, e.g. to keep them apart from
+    /// other data in a shared Redis.
+    ///
+    /// Keys are laid out as `prefix:name:algorithm:key`, leaving out the prefix and name when
+    /// unset, so that limiters never share counters with a different algorithm. Each rule counts
+    /// under that key in braces followed by the rule's index and period in milliseconds, e.g.
+    /// `{ratelimit:login:fixed_window:key}:0:60000`.
+    ///
+    /// ```
+    /// # use actix_limiter::{Limiter, MemoryBackend};
+    /// let limiter = Limiter::builder(MemoryBackend::new())
+    ///     .key_prefix("ratelimit")
+    ///     .name("login")
+    ///     .hash_keys_over(64)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn key_prefix(&mut self, prefix: impl Into<Cow<'static, str>>) -> &mut Self {
+        self.key_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Names this limiter, keeping its backend keys apart from other limiters'. See
+    /// [`key_prefix`](Self::key_prefix).
+    ///
+    /// Limiters sharing a backend, e.g. per-route limiters handed to
+    /// [`RateLimiter::new`](crate::RateLimiter::new), share the counters of their keys unless they
+    /// have different names or algorithms.
+    pub fn name(&mut self, name: impl Into<Cow<'static, str>>) -> &mut Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Replaces keys longer than `max_len` bytes by their SHA-1 hex digest, bounding the size of
+    /// backend keys derived from e.g. tokens or URLs.
+    pub fn hash_keys_over(&mut self, max_len: usize) -> &mut Self {
+        self.hash_keys_over = Some(max_len);
         self
     }
 
@@ -70,9 +471,15 @@ impl Builder {
 
     /// Finalizes and returns a `Limiter`.
     ///
-    /// Note that this method will connect to the Redis server to test its connection which is a
-    /// **synchronous** operation.
+    /// Fails when a rule has a zero period, which could never reset, or when a token bucket rule
+    /// would never refill, i.e. refills 0 tokens.
     pub fn build(&mut self) -> Result<Limiter, Error> {
+        let rules: Vec<Rule> = std::iter::once(Rule::new(self.limit, self.period))
+            .chain(self.rules.iter().copied())
+            .collect();
+
+        self.algorithm.check_rules(&rules)?;
+
         let get_key = if let Some(resolver) = self.get_key_fn.clone() {
             resolver
         } else {
@@ -96,11 +503,39 @@ impl Builder {
             closure
         };
 
+        let namespace = [self.key_prefix.as_deref(), self.name.as_deref()]
+            .into_iter()
+            .flatten()
+            .chain([self.algorithm.name()])
+            .collect::<Vec<_>>()
+            .join(":");
+
         Ok(Limiter {
-            pool: self.redis.clone(),
-            limit: self.limit,
-            period: self.period,
+            backend: self.backend.clone(),
+            rules,
+            algorithm: self.algorithm,
             get_key_fn: get_key,
+            get_async_key_fn: self.get_async_key_fn.clone(),
+            get_rules_fn: self.get_rules_fn.clone(),
+            get_cost_fn: self.get_cost_fn.clone(),
+            allow: self.allow.clone(),
+            deny: self.deny.clone(),
+            allow_networks: self.allow_networks.clone(),
+            deny_networks: self.deny_networks.clone(),
+            get_client_ip_fn: self.get_client_ip_fn.clone(),
+            header_format: self.header_format,
+            limit_exceeded_fn: self.limit_exceeded_fn.clone(),
+            failure_policy: self.failure_policy,
+            fallback: match self.failure_policy {
+                FailurePolicy::Fallback => Some(Arc::new(MemoryBackend::new())),
+                _ => None,
+            },
+            timeout: self.timeout,
+            circuit_breaker: self.circuit_breaker.map(|(failure_threshold, reset_timeout)| {
+                Arc::new(CircuitBreaker::new(failure_threshold, reset_timeout))
+            }),
+            namespace,
+            hash_keys_over: self.hash_keys_over,
         })
     }
-}
\ No newline at end of file
+}
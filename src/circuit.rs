@@ -0,0 +1,114 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Circuit breaker state.
+#[derive(Debug, Clone, Copy)]
+enum State {
+    /// Calls go through; counts consecutive failures.
+    Closed { failures: u32 },
+    /// Calls are short-circuited until the instant has passed.
+    Open { until: Instant },
+    /// A single probe call is in flight since the instant.
+    HalfOpen { since: Instant },
+}
+
+/// Stops calling a failing backend after `failure_threshold` consecutive failures, then lets a
+/// single probe through every `reset_timeout` until one succeeds.
+#[derive(Debug)]
+pub(crate) struct CircuitBreaker {
+    failure_threshold: u32,
+    reset_timeout: Duration,
+    state: Mutex<State>,
+}
+
+impl CircuitBreaker {
+    pub(crate) fn new(failure_threshold: u32, reset_timeout: Duration) -> Self {
+        CircuitBreaker {
+            failure_threshold: failure_threshold.max(1),
+            reset_timeout,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// Returns whether a call may go through, letting a probe through once the circuit has been
+    /// open for `reset_timeout`. A probe that never reports back is replaced after the same delay.
+    pub(crate) fn allow(&self) -> bool {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        match *state {
+            State::Closed { .. } => true,
+            State::Open { until } if now < until => false,
+            State::HalfOpen { since } if now < since + self.reset_timeout => false,
+            State::Open { .. } | State::HalfOpen { .. } => {
+                *state = State::HalfOpen { since: now };
+                true
+            }
+        }
+    }
+
+    /// Records the outcome of an allowed call.
+    pub(crate) fn record(&self, success: bool) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        *state = match (*state, success) {
+            (_, true) => State::Closed { failures: 0 },
+            (State::Closed { failures }, false) if failures + 1 < self.failure_threshold => {
+                State::Closed {
+                    failures: failures + 1,
+                }
+            }
+            (_, false) => {
+                log::warn!("Rate limit backend failing, opening circuit breaker");
+                State::Open {
+                    until: Instant::now() + self.reset_timeout,
+                }
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+
+    use super::*;
+
+    #[test]
+    fn test_opens_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+
+        assert!(breaker.allow());
+        breaker.record(false);
+        breaker.record(true);
+        breaker.record(false);
+        assert!(breaker.allow());
+
+        breaker.record(false);
+        assert!(!breaker.allow());
+    }
+
+    #[test]
+    fn test_half_opens_to_probe() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(50));
+
+        breaker.record(false);
+        assert!(!breaker.allow());
+
+        sleep(Duration::from_millis(60));
+        assert!(breaker.allow());
+        // only one probe at a time
+        assert!(!breaker.allow());
+
+        breaker.record(false);
+        assert!(!breaker.allow());
+
+        sleep(Duration::from_millis(60));
+        assert!(breaker.allow());
+        breaker.record(true);
+        assert!(breaker.allow());
+        assert!(breaker.allow());
+    }
+}
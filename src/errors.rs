@@ -17,6 +17,16 @@ pub enum Error {
     #[from(ignore)]
     LimitExceeded(#[error(not(source))] Status),
 
+    /// Backend call did not complete within the limiter's timeout.
+    #[display("Rate limit backend timed out")]
+    #[from(ignore)]
+    Timeout,
+
+    /// Backend call was skipped because the circuit breaker is open.
+    #[display("Rate limit circuit breaker is open")]
+    #[from(ignore)]
+    CircuitOpen,
+
     /// Time conversion failed.
     #[display("Time conversion failed")]
     Time(time::error::ComponentRange),
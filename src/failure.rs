@@ -0,0 +1,17 @@
+/// Behavior of the middleware when the backend fails, e.g. while Redis is unreachable.
+///
+/// Rejections because a limit is exceeded are not failures and are unaffected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FailurePolicy {
+    /// Allows requests without rate limit headers, logging the error.
+    FailOpen,
+
+    /// Rejects requests with `503 Service Unavailable`.
+    #[default]
+    FailClosed,
+
+    /// Counts requests in an in-process [`MemoryBackend`](crate::MemoryBackend) until the backend
+    /// recovers, so limits hold per application instance in the meantime.
+    Fallback,
+}
@@ -0,0 +1,174 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER};
+
+use crate::status::Status;
+
+const X_RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+const X_RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+const X_RATELIMIT_RESET: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+const RATELIMIT: HeaderName = HeaderName::from_static("ratelimit");
+const RATELIMIT_POLICY: HeaderName = HeaderName::from_static("ratelimit-policy");
+
+/// Name of the quota policy reported in the draft header fields.
+const POLICY_NAME: &str = "default";
+
+/// Rate limit header fields attached to responses.
+///
+/// Both formats also set `Retry-After` to the number of seconds until the current period resets,
+/// or until the next request is allowed for rejected requests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HeaderFormat {
+    /// `X-RateLimit-Limit`, `X-RateLimit-Remaining` and `X-RateLimit-Reset` (UNIX timestamp).
+    #[default]
+    Legacy,
+
+    /// `RateLimit` and `RateLimit-Policy` structured fields, as described by the IETF
+    /// [draft](https://datatracker.ietf.org/doc/draft-ietf-httpapi-ratelimit-headers/).
+    Draft,
+}
+
+impl HeaderFormat {
+    /// Inserts header fields describing `status` for a limit over its rule's period, or over
+    /// `period` when the status does not say.
+    pub(crate) fn insert(self, headers: &mut HeaderMap, status: &Status, period: Duration) {
+        let period = status.period().unwrap_or(period);
+        let reset_after = reset_after_secs(status);
+        let retry_after = retry_after_secs(status);
+
+        match self {
+            HeaderFormat::Legacy => {
+                headers.insert(X_RATELIMIT_LIMIT, status.limit().into());
+                headers.insert(X_RATELIMIT_REMAINING, status.remaining().into());
+                headers.insert(X_RATELIMIT_RESET, status.reset_epoch_utc().into());
+            }
+            HeaderFormat::Draft => {
+                let policy = format!(
+                    "\"{POLICY_NAME}\";q={};w={}",
+                    status.limit(),
+                    period.as_millis().div_ceil(1000)
+                );
+                let limit = format!("\"{POLICY_NAME}\";r={};t={reset_after}", status.remaining());
+
+                // structured field strings and integers are always valid header values
+                headers.insert(RATELIMIT_POLICY, HeaderValue::from_str(&policy).unwrap());
+                headers.insert(RATELIMIT, HeaderValue::from_str(&limit).unwrap());
+            }
+        }
+
+        headers.insert(RETRY_AFTER, retry_after.into());
+    }
+}
+
+/// Returns the whole seconds until the period of `status` resets; sub-second waits round up.
+///
+/// Uses the relative reset of the status when known, so that a backend clock differing from the
+/// local one does not skew the result, and the reset timestamp against the local clock otherwise.
+fn reset_after_secs(status: &Status) -> u64 {
+    if let Some(reset_after) = status.reset_after() {
+        return reset_after.as_millis().div_ceil(1000) as u64;
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    status
+        .reset_epoch_utc_ms()
+        .saturating_sub(now)
+        .div_ceil(1000)
+}
+
+/// Returns the whole seconds until the next request is allowed for rejected statuses, or until
+/// the period resets otherwise; sub-second waits round up.
+pub(crate) fn retry_after_secs(status: &Status) -> u64 {
+    status.retry_after().map_or_else(
+        || reset_after_secs(status),
+        |d| d.as_millis().div_ceil(1000) as u64,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status() -> Status {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as usize;
+        Status::new(40, 100, now + 30)
+    }
+
+    #[test]
+    fn test_legacy_headers() {
+        let status = status();
+        let mut headers = HeaderMap::new();
+        HeaderFormat::Legacy.insert(&mut headers, &status, Duration::from_secs(60));
+
+        assert_eq!(headers.get("x-ratelimit-limit").unwrap(), "100");
+        assert_eq!(headers.get("x-ratelimit-remaining").unwrap(), "60");
+        assert_eq!(
+            headers.get("x-ratelimit-reset").unwrap(),
+            status.reset_epoch_utc().to_string().as_str()
+        );
+        assert!(headers.contains_key("retry-after"));
+        assert!(!headers.contains_key("ratelimit"));
+    }
+
+    #[test]
+    fn test_draft_headers() {
+        let mut headers = HeaderMap::new();
+        HeaderFormat::Draft.insert(&mut headers, &status(), Duration::from_secs(60));
+
+        assert_eq!(
+            headers.get("ratelimit-policy").unwrap(),
+            "\"default\";q=100;w=60"
+        );
+        let limit = headers.get("ratelimit").unwrap().to_str().unwrap();
+        assert!(limit.starts_with("\"default\";r=60;t="));
+        assert!(headers.contains_key("retry-after"));
+        assert!(!headers.contains_key("x-ratelimit-limit"));
+    }
+
+    #[test]
+    fn test_retry_after_rounds_up() {
+        let status = status().with_retry_after(Duration::from_millis(1200));
+        let mut headers = HeaderMap::new();
+        HeaderFormat::Legacy.insert(&mut headers, &status, Duration::from_secs(60));
+
+        assert_eq!(headers.get("retry-after").unwrap(), "2");
+    }
+
+    #[test]
+    fn test_sub_second_period() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        let status = Status::new(1, 5, 0).with_reset_epoch_utc_ms(now + 400);
+        let mut headers = HeaderMap::new();
+        HeaderFormat::Draft.insert(&mut headers, &status, Duration::from_millis(500));
+
+        assert_eq!(
+            headers.get("ratelimit-policy").unwrap(),
+            "\"default\";q=5;w=1"
+        );
+        assert_eq!(headers.get("ratelimit").unwrap(), "\"default\";r=4;t=1");
+        assert_eq!(headers.get("retry-after").unwrap(), "1");
+    }
+
+    #[test]
+    fn test_reset_after_ignores_clock_skew() {
+        // a reset timed by a backend clock an hour ahead of the local one
+        let status = status();
+        let status = Status::new(40, 100, status.reset_epoch_utc() + 3_600)
+            .with_reset_after(Duration::from_millis(29_200));
+        let mut headers = HeaderMap::new();
+        HeaderFormat::Draft.insert(&mut headers, &status, Duration::from_secs(60));
+
+        assert_eq!(headers.get("ratelimit").unwrap(), "\"default\";r=60;t=30");
+        assert_eq!(headers.get("retry-after").unwrap(), "30");
+    }
+}
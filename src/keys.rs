@@ -0,0 +1,358 @@
+//! Ready-made key extractors for [`Builder::key_by`](crate::Builder::key_by).
+//!
+//! Extractors compose with [`KeyExtractor::and`], e.g. to limit each client per endpoint:
+//!
+//! ```
+//! # use actix_limiter::{keys::{self, KeyExtractor as _}, Limiter, MemoryBackend};
+//! let limiter = Limiter::builder(MemoryBackend::new())
+//!     .key_by(keys::peer_ip().and(keys::path_and_method()))
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+};
+
+use actix_web::{
+    HttpMessage as _,
+    dev::ServiceRequest,
+    http::header::{FORWARDED, X_FORWARDED_FOR},
+    web,
+};
+pub use ipnet::IpNet;
+
+/// Derives the rate limit key of a request; `None` leaves the request unlimited.
+///
+/// Implemented by every suitable closure, so custom extractors compose with the built-in ones.
+pub trait KeyExtractor: Fn(&ServiceRequest) -> Option<String> + Send + Sync + 'static {
+    /// Joins the keys of `self` and `other`, yielding `None` when either does.
+    ///
+    /// The first key is prefixed with its length, e.g. `6:secret|GET /items`, so that clients
+    /// cannot make two different pairs of keys collide by moving a `|` between them.
+    fn and<K: KeyExtractor>(self, other: K) -> impl KeyExtractor
+    where
+        Self: Sized,
+    {
+        move |req: &ServiceRequest| {
+            let (first, second) = (self(req)?, other(req)?);
+            Some(format!("{}:{first}|{second}", first.len()))
+        }
+    }
+}
+
+impl<T> KeyExtractor for T where T: Fn(&ServiceRequest) -> Option<String> + Send + Sync + 'static {}
+
+/// Keys requests by the IP address of the connected peer.
+///
+/// Behind a reverse proxy this is the proxy's address; see [`real_ip`].
+pub fn peer_ip() -> impl KeyExtractor {
+    |req: &ServiceRequest| Some(req.peer_addr()?.ip().to_string())
+}
+
+/// Header in which reverse proxies report the forwarded chain, see [`real_ip`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ForwardedHeader {
+    /// The standard `Forwarded` header (RFC 7239).
+    Forwarded,
+
+    /// The de facto `X-Forwarded-For` header.
+    XForwardedFor,
+}
+
+/// Keys requests by the client IP address, taken from the forwarded chain in `header` when the
+/// connected peer is in one of the `trusted_proxies` networks.
+///
+/// The chain is walked from the right, skipping the hops appended by trusted proxies, and the
+/// first untrusted hop is the client. Entries further left were supplied by the client itself and
+/// are never used, so clients cannot pick their key by spoofing the headers. Set `header` to the
+/// one your proxies write; the other is never read, so clients cannot slip in a chain through it.
+/// Requests from untrusted peers are keyed by the peer address.
+///
+/// ```
+/// # use actix_limiter::{keys::{self, ForwardedHeader, IpNet}, Limiter, MemoryBackend};
+/// let trusted_proxies: [IpNet; 2] = ["10.0.0.0/8".parse().unwrap(), "fd00::/8".parse().unwrap()];
+/// let limiter = Limiter::builder(MemoryBackend::new())
+///     .key_by(keys::real_ip(ForwardedHeader::XForwardedFor, trusted_proxies))
+///     .build()
+///     .unwrap();
+/// ```
+pub fn real_ip(
+    header: ForwardedHeader,
+    trusted_proxies: impl IntoIterator<Item = IpNet>,
+) -> impl KeyExtractor {
+    let trusted_proxies: Vec<IpNet> = trusted_proxies.into_iter().collect();
+    let is_trusted = move |ip: &IpAddr| trusted_proxies.iter().any(|net| net.contains(ip));
+
+    move |req: &ServiceRequest| {
+        let mut client = req.peer_addr()?.ip().to_canonical();
+
+        for hop in forwarded_chain(req, header).iter().rev() {
+            if !is_trusted(&client) {
+                break;
+            }
+            // a hop that is not an address, e.g. `unknown`, ends the chain at its proxy
+            match parse_hop(hop) {
+                Some(ip) => client = ip,
+                None => break,
+            }
+        }
+
+        Some(client.to_string())
+    }
+}
+
+/// Returns the hops of the forwarded chain in `header`, from the client to the last proxy.
+fn forwarded_chain(req: &ServiceRequest, header: ForwardedHeader) -> Vec<String> {
+    let headers = req.headers();
+
+    match header {
+        ForwardedHeader::Forwarded => headers
+            .get_all(FORWARDED)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.trim().split_once('='))
+                    .find(|(name, _)| name.eq_ignore_ascii_case("for"))
+                    .map(|(_, node)| node.trim_matches('"').to_owned())
+                    .unwrap_or_default()
+            })
+            .collect(),
+        ForwardedHeader::XForwardedFor => headers
+            .get_all(X_FORWARDED_FOR)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|hop| hop.trim().to_owned())
+            .collect(),
+    }
+}
+
+/// Parses a forwarded hop: a bare address, or one with a port or IPv6 brackets.
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    let ip = hop
+        .parse::<IpAddr>()
+        .ok()
+        .or_else(|| hop.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| hop.strip_prefix('[')?.strip_suffix(']')?.parse().ok())?;
+    Some(ip.to_canonical())
+}
+
+/// Groups IPv6 addresses keyed by `extractor` into networks of `prefix_len` bits, e.g. 64 for
+/// the usual customer allocation, so that a client cannot rotate through its own addresses.
+///
+/// IPv4 addresses and other keys are left unchanged.
+pub fn ipv6_prefix(extractor: impl KeyExtractor, prefix_len: u8) -> impl KeyExtractor {
+    let mask = u128::MAX
+        .checked_shl(128 - u32::from(prefix_len.min(128)))
+        .unwrap_or(0);
+
+    move |req: &ServiceRequest| {
+        let key = extractor(req)?;
+        match key.parse::<Ipv6Addr>() {
+            Ok(ip) if ip.to_ipv4_mapped().is_none() => {
+                let network = Ipv6Addr::from(u128::from(ip) & mask);
+                Some(format!("{network}/{prefix_len}"))
+            }
+            _ => Some(key),
+        }
+    }
+}
+
+/// Keys requests by the value of the `name` header, e.g. an API key.
+pub fn header(name: &'static str) -> impl KeyExtractor {
+    move |req: &ServiceRequest| Some(req.headers().get(name)?.to_str().ok()?.to_owned())
+}
+
+/// Keys requests by method and path, e.g. `GET /users`, to limit each endpoint separately.
+pub fn path_and_method() -> impl KeyExtractor {
+    |req: &ServiceRequest| Some(format!("{} {}", req.method(), req.path()))
+}
+
+/// Keys requests by the value of the `name` query parameter.
+pub fn query(name: &'static str) -> impl KeyExtractor {
+    move |req: &ServiceRequest| {
+        web::Query::<HashMap<String, String>>::from_query(req.query_string())
+            .ok()?
+            .remove(name)
+    }
+}
+
+/// Keys requests by the authenticated identity `T` that an earlier middleware stored in the
+/// request extensions, e.g. the user of a session or token.
+///
+/// ```
+/// # use actix_limiter::keys;
+/// struct User {
+///     id: u64,
+/// }
+///
+/// let key_by = keys::extension(|user: &User| user.id.to_string());
+/// ```
+pub fn extension<T, F>(f: F) -> impl KeyExtractor
+where
+    T: 'static,
+    F: Fn(&T) -> String + Send + Sync + 'static,
+{
+    move |req: &ServiceRequest| req.extensions().get::<T>().map(&f)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    #[test]
+    fn test_peer_ip() {
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:1234".parse().unwrap())
+            .to_srv_request();
+        assert_eq!(peer_ip()(&req).as_deref(), Some("10.0.0.1"));
+
+        assert_eq!(peer_ip()(&TestRequest::default().to_srv_request()), None);
+    }
+
+    #[test]
+    fn test_real_ip_trusts_only_proxies() {
+        let extractor = real_ip(
+            ForwardedHeader::XForwardedFor,
+            ["10.0.0.0/8".parse().unwrap()],
+        );
+
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:1234".parse().unwrap())
+            .insert_header(("x-forwarded-for", "203.0.113.7"))
+            .to_srv_request();
+        assert_eq!(extractor(&req).as_deref(), Some("203.0.113.7"));
+
+        let req = TestRequest::default()
+            .peer_addr("198.51.100.2:1234".parse().unwrap())
+            .insert_header(("x-forwarded-for", "203.0.113.7"))
+            .to_srv_request();
+        assert_eq!(extractor(&req).as_deref(), Some("198.51.100.2"));
+    }
+
+    #[test]
+    fn test_real_ip_walks_chain_from_the_right() {
+        let trusted: [IpNet; 2] = [
+            "10.0.0.0/8".parse().unwrap(),
+            "2001:db8::/32".parse().unwrap(),
+        ];
+        let extractor = real_ip(ForwardedHeader::XForwardedFor, trusted);
+        let req = |name, value| {
+            TestRequest::default()
+                .peer_addr("10.0.0.1:1234".parse().unwrap())
+                .insert_header((name, value))
+                .to_srv_request()
+        };
+
+        // the spoofed leftmost entry is ignored
+        let xff = req("x-forwarded-for", "1.1.1.1, 203.0.113.7, 10.0.0.2");
+        assert_eq!(extractor(&xff).as_deref(), Some("203.0.113.7"));
+
+        let forwarded = req(
+            "forwarded",
+            r#"for=1.1.1.1, for="203.0.113.7:4711";proto=https, for="[2001:db8::1]""#,
+        );
+        let from_forwarded = real_ip(ForwardedHeader::Forwarded, trusted);
+        assert_eq!(from_forwarded(&forwarded).as_deref(), Some("203.0.113.7"));
+
+        // a chain of trusted hops keys by the leftmost one
+        let internal = req("x-forwarded-for", "10.0.0.3, 10.0.0.2");
+        assert_eq!(extractor(&internal).as_deref(), Some("10.0.0.3"));
+
+        // an unknown hop keys by the proxy reporting it
+        let unknown = req("x-forwarded-for", "1.1.1.1, unknown, 10.0.0.2");
+        assert_eq!(extractor(&unknown).as_deref(), Some("10.0.0.2"));
+    }
+
+    #[test]
+    fn test_real_ip_reads_only_the_configured_header() {
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:1234".parse().unwrap())
+            .insert_header(("x-forwarded-for", "203.0.113.7"))
+            .insert_header(("forwarded", "for=6.6.6.6"))
+            .to_srv_request();
+
+        let trusted: [IpNet; 1] = ["10.0.0.0/8".parse().unwrap()];
+        let xff = real_ip(ForwardedHeader::XForwardedFor, trusted);
+        assert_eq!(xff(&req).as_deref(), Some("203.0.113.7"));
+
+        let forwarded = real_ip(ForwardedHeader::Forwarded, trusted);
+        assert_eq!(forwarded(&req).as_deref(), Some("6.6.6.6"));
+    }
+
+    #[test]
+    fn test_ipv6_prefix() {
+        let extractor = ipv6_prefix(peer_ip(), 64);
+
+        let req = TestRequest::default()
+            .peer_addr("[2001:db8:1:2:3:4:5:6]:1234".parse().unwrap())
+            .to_srv_request();
+        assert_eq!(extractor(&req).as_deref(), Some("2001:db8:1:2::/64"));
+
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:1234".parse().unwrap())
+            .to_srv_request();
+        assert_eq!(extractor(&req).as_deref(), Some("10.0.0.1"));
+    }
+
+    #[test]
+    fn test_request_parts() {
+        let req = TestRequest::post()
+            .uri("/search?tenant=acme&q=rust")
+            .insert_header(("x-api-key", "secret"))
+            .to_srv_request();
+
+        assert_eq!(header("x-api-key")(&req).as_deref(), Some("secret"));
+        assert_eq!(header("x-missing")(&req), None);
+        assert_eq!(path_and_method()(&req).as_deref(), Some("POST /search"));
+        assert_eq!(query("tenant")(&req).as_deref(), Some("acme"));
+        assert_eq!(query("page")(&req), None);
+    }
+
+    #[test]
+    fn test_extension() {
+        struct User(u64);
+
+        let req = TestRequest::default().to_srv_request();
+        let extractor = extension(|user: &User| user.0.to_string());
+        assert_eq!(extractor(&req), None);
+
+        req.extensions_mut().insert(User(42));
+        assert_eq!(extractor(&req).as_deref(), Some("42"));
+    }
+
+    #[test]
+    fn test_and() {
+        let req = TestRequest::get()
+            .uri("/items")
+            .insert_header(("x-api-key", "secret"))
+            .to_srv_request();
+
+        let extractor = header("x-api-key").and(path_and_method());
+        assert_eq!(extractor(&req).as_deref(), Some("6:secret|GET /items"));
+
+        let extractor = header("x-missing").and(path_and_method());
+        assert_eq!(extractor(&req), None);
+    }
+
+    #[test]
+    fn test_and_does_not_collide() {
+        let extractor = header("x-api-key").and(query("t"));
+        let key = |api_key, t| {
+            let req = TestRequest::get()
+                .uri(&format!("/?t={t}"))
+                .insert_header(("x-api-key", api_key))
+                .to_srv_request();
+            extractor(&req).unwrap()
+        };
+
+        assert_eq!(key("a|b", "c"), "3:a|b|c");
+        assert_eq!(key("a", "b|c"), "1:a|b|c");
+    }
+}
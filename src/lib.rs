@@ -1,33 +1,35 @@
-use std::{borrow::Cow, fmt, sync::Arc, time::{Duration, SystemTime, UNIX_EPOCH}};
+use std::{borrow::Cow, fmt, future::Future, net::IpAddr, pin::Pin, sync::Arc, time::Duration};
 
-use actix_web::dev::ServiceRequest;
-use deadpool_redis::{Pool};
+use actix_web::{HttpResponse, dev::ServiceRequest};
 
+use crate::{circuit::CircuitBreaker, keys::IpNet};
+
+mod algorithm;
+mod backend;
 mod builder;
+mod circuit;
 mod errors;
+mod failure;
+mod headers;
+pub mod keys;
 mod middleware;
+mod response;
+mod rule;
 mod status;
+#[cfg(test)]
+mod testing;
 
-pub use self::{builder::Builder, errors::Error, middleware::RateLimiter, status::Status};
-
-const LUA: &str = r#"
-local key   = KEYS[1]
-local limit = tonumber(ARGV[1])
-local win   = tonumber(ARGV[2])
-local now   = tonumber(ARGV[3])
-
-local cnt = redis.call("INCR", key)
-if cnt == 1 then
-    redis.call("EXPIRE", key, win)
-end
-
-local ttl = redis.call("TTL", key)
-if ttl < 0 then ttl = win end
-
-local limited = cnt > limit and 1 or 0
-local remaining = limited == 1 and 0 or (limit - cnt)
-return {limited, remaining, now + ttl}
-"#;
+pub use self::{
+    algorithm::Algorithm,
+    backend::{Backend, BoxFuture, MemoryBackend, MemoryBackendBuilder, RedisBackend},
+    builder::Builder,
+    errors::Error,
+    failure::FailurePolicy,
+    headers::HeaderFormat,
+    middleware::RateLimiter,
+    rule::Rule,
+    status::Status,
+};
 
 /// Default request limit.
 pub const DEFAULT_REQUEST_LIMIT: usize = 5000;
@@ -60,53 +62,635 @@ impl fmt::Debug for GetKeyFn {
 /// Wrapped Get key function Trait
 type GetArcBoxKeyFn = Arc<GetKeyFn>;
 
+/// An owned, dynamically typed future that need not be `Send`, as returned by async key
+/// resolvers.
+pub type LocalBoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// Helper trait to impl Debug on GetAsyncKeyFn type
+trait GetAsyncKeyFnT: for<'a> Fn(&'a ServiceRequest) -> LocalBoxFuture<'a, Option<String>> {}
+
+impl<T> GetAsyncKeyFnT for T where
+    T: for<'a> Fn(&'a ServiceRequest) -> LocalBoxFuture<'a, Option<String>>
+{
+}
+
+/// Get async key function type with auto traits
+type GetAsyncKeyFn = dyn GetAsyncKeyFnT + Send + Sync;
+
+impl fmt::Debug for GetAsyncKeyFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GetAsyncKeyFn")
+    }
+}
+
+/// Wrapped Get async key function Trait
+type GetArcAsyncKeyFn = Arc<GetAsyncKeyFn>;
+
+/// Helper trait to impl Debug on GetRulesFn type
+trait GetRulesFnT: Fn(&ServiceRequest) -> Option<Vec<Rule>> {}
+
+impl<T> GetRulesFnT for T where T: Fn(&ServiceRequest) -> Option<Vec<Rule>> {}
+
+/// Get rules function type with auto traits
+type GetRulesFn = dyn GetRulesFnT + Send + Sync;
+
+impl fmt::Debug for GetRulesFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GetRulesFn")
+    }
+}
+
+/// Wrapped Get rules function Trait
+type GetArcRulesFn = Arc<GetRulesFn>;
+
+/// Helper trait to impl Debug on GetCostFn type
+trait GetCostFnT: Fn(&ServiceRequest) -> usize {}
+
+impl<T> GetCostFnT for T where T: Fn(&ServiceRequest) -> usize {}
+
+/// Get cost function type with auto traits
+type GetCostFn = dyn GetCostFnT + Send + Sync;
+
+impl fmt::Debug for GetCostFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GetCostFn")
+    }
+}
+
+/// Wrapped Get cost function Trait
+type GetArcCostFn = Arc<GetCostFn>;
+
+/// Helper trait to impl Debug on FilterFn type
+trait FilterFnT: Fn(&ServiceRequest, &str) -> bool {}
+
+impl<T> FilterFnT for T where T: Fn(&ServiceRequest, &str) -> bool {}
+
+/// Request filter function type with auto traits
+type FilterFn = dyn FilterFnT + Send + Sync;
+
+impl fmt::Debug for FilterFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FilterFn")
+    }
+}
+
+/// Wrapped request filter function Trait
+type GetArcFilterFn = Arc<FilterFn>;
+
+/// Helper trait to impl Debug on LimitExceededFn type
+trait LimitExceededFnT: Fn(&ServiceRequest, &Status) -> HttpResponse {}
+
+impl<T> LimitExceededFnT for T where T: Fn(&ServiceRequest, &Status) -> HttpResponse {}
+
+/// Limit exceeded response function type with auto traits
+type LimitExceededFn = dyn LimitExceededFnT + Send + Sync;
+
+impl fmt::Debug for LimitExceededFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LimitExceededFn")
+    }
+}
+
+/// Wrapped limit exceeded response function Trait
+type GetArcLimitExceededFn = Arc<LimitExceededFn>;
+
 /// Rate limiter.
 #[derive(Debug, Clone)]
 pub struct Limiter {
-    pool: Arc<Pool>,
-    limit: usize,
-    period: Duration,
+    backend: Arc<dyn Backend>,
+    rules: Vec<Rule>,
+    algorithm: Algorithm,
     get_key_fn: GetArcBoxKeyFn,
+    get_async_key_fn: Option<GetArcAsyncKeyFn>,
+    get_rules_fn: Option<GetArcRulesFn>,
+    get_cost_fn: Option<GetArcCostFn>,
+    allow: Vec<GetArcFilterFn>,
+    deny: Vec<GetArcFilterFn>,
+    allow_networks: Vec<IpNet>,
+    deny_networks: Vec<IpNet>,
+    get_client_ip_fn: Option<GetArcBoxKeyFn>,
+    header_format: HeaderFormat,
+    limit_exceeded_fn: Option<GetArcLimitExceededFn>,
+    failure_policy: FailurePolicy,
+    fallback: Option<Arc<dyn Backend>>,
+    timeout: Option<Duration>,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    namespace: String,
+    hash_keys_over: Option<usize>,
 }
 
 impl Limiter {
-    /// Construct rate limiter builder with defaults.
-    ///
-    /// See [`redis-rs` docs](https://docs.rs/redis/0.21/redis/#connection-parameters) on connection
-    /// parameters for how to set the Redis URL.
+    /// Construct rate limiter builder with defaults, storing counters in `backend`.
     #[must_use]
-    pub fn builder(r: Arc<Pool>) -> Builder {
+    pub fn builder(backend: impl Backend) -> Builder {
         Builder {
-            redis: r,
+            backend: Arc::new(backend),
             limit: DEFAULT_REQUEST_LIMIT,
             period: Duration::from_secs(DEFAULT_PERIOD_SECS),
+            rules: Vec::new(),
+            algorithm: Algorithm::default(),
             get_key_fn: None,
+            get_async_key_fn: None,
+            get_rules_fn: None,
+            get_cost_fn: None,
+            allow: Vec::new(),
+            deny: Vec::new(),
+            allow_networks: Vec::new(),
+            deny_networks: Vec::new(),
+            get_client_ip_fn: None,
+            header_format: HeaderFormat::default(),
+            limit_exceeded_fn: None,
+            failure_policy: FailurePolicy::default(),
+            timeout: None,
+            circuit_breaker: None,
+            key_prefix: None,
+            name: None,
+            hash_keys_over: None,
             cookie_name: Cow::Borrowed(DEFAULT_COOKIE_NAME),
             #[cfg(feature = "session")]
             session_key: Cow::Borrowed(DEFAULT_SESSION_KEY),
         }
     }
 
-    /// Consumes one rate limit unit, returning the status.
-    pub async fn count(&self, key: impl Into<String>) -> Result<(bool, usize, usize), Error> {
+    /// Consumes one rate limit unit from every rule, returning the status of the most restrictive
+    /// rule.
+    ///
+    /// Returns [`Error::LimitExceeded`] carrying the status once the key has used up the limit of
+    /// any rule for its current period; the unit is then not consumed from the other rules.
+    ///
+    /// Backend calls fail with [`Error::Timeout`] past the configured timeout, and with
+    /// [`Error::CircuitOpen`] while the circuit breaker is open. With [`FailurePolicy::Fallback`],
+    /// backend failures are retried against the in-process fallback backend.
+    pub async fn count(&self, key: impl Into<String>) -> Result<Status, Error> {
+        self.count_n(key, 1).await
+    }
+
+    /// Consumes `cost` rate limit units from every rule at once, e.g. for expensive requests.
+    ///
+    /// The hit is rejected as a whole when the cost does not fit in the remaining allowance of any
+    /// rule. See [`count`](Self::count).
+    pub async fn count_n(&self, key: impl Into<String>, cost: usize) -> Result<Status, Error> {
         let key = key.into();
-        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as usize;
-        let win = self.period.as_secs() as usize;
-
-        let mut conn = self.pool.get().await?;
-        let res: Vec<i64> = redis::cmd("EVAL")
-            .arg(LUA)
-            .arg(1)                       // number of keys
-            .arg(&key)                    // KEYS[1]
-            .arg(self.limit as i64)       // ARGV[1]
-            .arg(win as i64)              // ARGV[2]
-            .arg(now as i64)              // ARGV[3]
-            .query_async(&mut *conn)   
-            .await?;
-
-        let limited = res[0] == 1;
-        let remaining = res[1] as usize;
-        let reset = res[2] as usize;
-        Ok((limited, remaining, reset))
-    }
-}
\ No newline at end of file
+        self.count_rules(&key, &self.rules, cost).await
+    }
+
+    /// Returns the current status of `key` without consuming any rate limit units, e.g. to show
+    /// the remaining allowance on a dashboard.
+    ///
+    /// The status is read atomically for every rule and algorithm, and reports the most
+    /// restrictive rule like [`count`](Self::count); a key that used up its limit is reported
+    /// with no remaining units rather than as an error.
+    pub async fn peek(&self, key: impl Into<String>) -> Result<Status, Error> {
+        let key = key.into();
+        match self.count_rules(&key, &self.rules, 0).await {
+            Err(Error::LimitExceeded(status)) => Ok(status),
+            res => res,
+        }
+    }
+
+    /// Consumes `cost` rate limit units from `rules` instead of the limiter's own rules.
+    pub(crate) async fn count_rules(
+        &self,
+        key: &str,
+        rules: &[Rule],
+        cost: usize,
+    ) -> Result<Status, Error> {
+        let key = &*self.storage_key(key);
+        let res = match &self.circuit_breaker {
+            Some(breaker) if !breaker.allow() => Err(Error::CircuitOpen),
+            Some(breaker) => {
+                let res = self.hit_backend(key, rules, cost).await;
+                breaker.record(matches!(res, Ok(_) | Err(Error::LimitExceeded(_))));
+                res
+            }
+            None => self.hit_backend(key, rules, cost).await,
+        };
+
+        match res {
+            Err(err) if !matches!(err, Error::LimitExceeded(_)) => match &self.fallback {
+                Some(fallback) => {
+                    log::warn!("Rate limit backend failed, counting in process: {}", err);
+                    fallback.hit(key, self.algorithm, rules, cost).await
+                }
+                None => Err(err),
+            },
+            res => res,
+        }
+    }
+
+    /// Returns the backend key of `key`: hashed when longer than the configured maximum, and
+    /// prefixed with the limiter's namespace.
+    fn storage_key(&self, key: &str) -> String {
+        match self.hash_keys_over {
+            Some(max_len) if key.len() > max_len => {
+                format!("{}:{}", self.namespace, sha1_smol::Sha1::from(key).digest())
+            }
+            _ => format!("{}:{key}", self.namespace),
+        }
+    }
+
+    /// Calls the backend, giving up after the configured timeout.
+    async fn hit_backend(&self, key: &str, rules: &[Rule], cost: usize) -> Result<Status, Error> {
+        let hit = self.backend.hit(key, self.algorithm, rules, cost);
+
+        match self.timeout {
+            Some(timeout) => actix_web::rt::time::timeout(timeout, hit)
+                .await
+                .unwrap_or(Err(Error::Timeout)),
+            None => hit.await,
+        }
+    }
+
+    /// Returns the behavior of the middleware when the backend fails.
+    #[must_use]
+    pub fn failure_policy(&self) -> FailurePolicy {
+        self.failure_policy
+    }
+
+    /// Resolves the key of `req`, awaiting the async key resolver if one is set.
+    pub(crate) async fn key_for(&self, req: &ServiceRequest) -> Option<String> {
+        match &self.get_async_key_fn {
+            Some(resolver) => resolver(req).await,
+            None => (self.get_key_fn)(req),
+        }
+    }
+
+    /// Resolves the number of units `req` consumes, 1 unless a cost resolver is set.
+    pub(crate) fn cost_for(&self, req: &ServiceRequest) -> usize {
+        self.get_cost_fn.as_ref().map_or(1, |resolver| resolver(req))
+    }
+
+    /// Returns whether `req` with `key` matches any allowlist entry, exempting it from limiting.
+    pub(crate) fn is_allowed(&self, req: &ServiceRequest, key: &str) -> bool {
+        self.allow.iter().any(|filter| filter(req, key))
+            || self.client_in_networks(req, &self.allow_networks)
+    }
+
+    /// Returns whether `req` with `key` matches any denylist entry, rejecting it outright.
+    pub(crate) fn is_denied(&self, req: &ServiceRequest, key: &str) -> bool {
+        self.deny.iter().any(|filter| filter(req, key))
+    }
+
+    /// Returns whether the client address of `req` is in a denied network, which is checked
+    /// before resolving the key.
+    pub(crate) fn is_denied_client(&self, req: &ServiceRequest) -> bool {
+        self.client_in_networks(req, &self.deny_networks)
+    }
+
+    /// Returns whether the client address of `req`, from the client IP resolver or else the
+    /// peer address, is within one of `networks`.
+    fn client_in_networks(&self, req: &ServiceRequest, networks: &[IpNet]) -> bool {
+        if networks.is_empty() {
+            return false;
+        }
+
+        let ip = match &self.get_client_ip_fn {
+            Some(resolver) => resolver(req).and_then(|ip| ip.parse::<IpAddr>().ok()),
+            None => req.peer_addr().map(|addr| addr.ip()),
+        };
+        ip.is_some_and(|ip| networks.iter().any(|net| net.contains(&ip.to_canonical())))
+    }
+
+    /// Builds the response to `req` when `status` is over the limit.
+    pub(crate) fn limit_exceeded_response(
+        &self,
+        req: &ServiceRequest,
+        status: &Status,
+    ) -> HttpResponse {
+        match &self.limit_exceeded_fn {
+            Some(respond) => respond(req, status),
+            None => response::problem_details(status),
+        }
+    }
+
+    /// Resolves the rules applying to `req`, if they differ from the limiter's own rules.
+    ///
+    /// Resolved rules that cannot be enforced, e.g. none at all or with a zero period, are logged
+    /// and replaced by the limiter's own rules instead of failing as a backend error.
+    pub(crate) fn rules_for(&self, req: &ServiceRequest) -> Option<Vec<Rule>> {
+        let rules = self.get_rules_fn.as_ref().and_then(|resolver| resolver(req))?;
+
+        match self.algorithm.check_rules(&rules) {
+            Ok(()) => Some(rules),
+            Err(err) => {
+                log::error!("Ignoring invalid rules from limit_by: {:?}", err);
+                None
+            }
+        }
+    }
+
+    /// Returns the rules enforced by this limiter, starting with the builder's `limit`/`period`.
+    #[must_use]
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::testing::FakeRedis;
+
+    fn limiter(redis: &FakeRedis, limit: usize) -> Limiter {
+        Limiter::builder(redis.backend())
+            .limit(limit)
+            .period(Duration::from_secs(60))
+            .build()
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_count_within_limit() {
+        let redis = FakeRedis::start();
+        let limiter = limiter(&redis, 2);
+        let key = uuid::Uuid::new_v4().to_string();
+
+        let status = limiter.count(&key).await.unwrap();
+        assert_eq!(status.limit(), 2);
+        assert_eq!(status.remaining(), 1);
+
+        let status = limiter.count(&key).await.unwrap();
+        assert_eq!(status.remaining(), 0);
+    }
+
+    #[actix_web::test]
+    async fn test_count_limit_exceeded() {
+        let redis = FakeRedis::start();
+        let limiter = limiter(&redis, 2);
+        let key = uuid::Uuid::new_v4().to_string();
+
+        limiter.count(&key).await.unwrap();
+        limiter.count(&key).await.unwrap();
+
+        match limiter.count(&key).await {
+            Err(Error::LimitExceeded(status)) => {
+                assert_eq!(status.limit(), 2);
+                assert_eq!(status.remaining(), 0);
+                assert!(status.reset_epoch_utc() > 0);
+            }
+            res => panic!("expected limit to be exceeded, got {res:?}"),
+        }
+    }
+
+    #[test]
+    fn test_build_rejects_token_bucket_without_refill() {
+        let second = Duration::from_secs(1);
+
+        assert!(Limiter::builder(Exhausted).token_bucket(20, 0, second).build().is_err());
+        assert!(
+            Limiter::builder(Exhausted)
+                .token_bucket(20, 5, Duration::ZERO)
+                .build()
+                .is_err()
+        );
+        assert!(Limiter::builder(Exhausted).token_bucket(20, 5, second).build().is_ok());
+    }
+
+    #[test]
+    fn test_build_rejects_zero_period() {
+        let algorithms = [
+            Algorithm::FixedWindow,
+            Algorithm::SlidingLog,
+            Algorithm::SlidingWindow,
+            Algorithm::Gcra,
+            Algorithm::LeakyBucket {
+                max_wait: Duration::from_secs(1),
+            },
+        ];
+
+        for algorithm in algorithms {
+            let mut builder = Limiter::builder(Exhausted);
+            builder.algorithm(algorithm);
+            assert!(builder.period(Duration::ZERO).build().is_err(), "{algorithm:?}");
+            builder.period(Duration::from_secs(1));
+            assert!(builder.rule(10, Duration::ZERO).build().is_err(), "{algorithm:?}");
+        }
+    }
+
+    #[derive(Debug)]
+    struct Exhausted;
+
+    impl Backend for Exhausted {
+        fn hit<'a>(
+            &'a self,
+            _key: &'a str,
+            _algorithm: Algorithm,
+            rules: &'a [Rule],
+            _cost: usize,
+        ) -> BoxFuture<'a, Result<Status, Error>> {
+            let limit = rules[0].limit();
+            Box::pin(async move { Err(Error::LimitExceeded(Status::new(limit + 1, limit, 0))) })
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_count_custom_backend() {
+        let limiter = Limiter::builder(Exhausted).limit(7).build().unwrap();
+
+        match limiter.count("key").await {
+            Err(Error::LimitExceeded(status)) => assert_eq!(status.limit(), 7),
+            res => panic!("expected limit to be exceeded, got {res:?}"),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_count_keys_are_independent() {
+        let redis = FakeRedis::start();
+        let limiter = limiter(&redis, 1);
+
+        limiter.count("a").await.unwrap();
+        limiter.count("b").await.unwrap();
+        assert!(matches!(
+            limiter.count("a").await,
+            Err(Error::LimitExceeded(_))
+        ));
+    }
+
+    /// Backend failing every hit after `delay`, counting its calls.
+    #[derive(Debug, Default)]
+    struct Failing {
+        delay: Duration,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Backend for Failing {
+        fn hit<'a>(
+            &'a self,
+            _key: &'a str,
+            _algorithm: Algorithm,
+            _rules: &'a [Rule],
+            _cost: usize,
+        ) -> BoxFuture<'a, Result<Status, Error>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                actix_web::rt::time::sleep(self.delay).await;
+                Err(Error::Other("unavailable".to_owned()))
+            })
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_count_timeout() {
+        let limiter = Limiter::builder(Failing {
+            delay: Duration::from_secs(10),
+            ..Failing::default()
+        })
+        .timeout(Duration::from_millis(20))
+        .build()
+        .unwrap();
+
+        assert!(matches!(limiter.count("key").await, Err(Error::Timeout)));
+    }
+
+    #[actix_web::test]
+    async fn test_count_circuit_breaker() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let limiter = Limiter::builder(Failing {
+            calls: Arc::clone(&calls),
+            ..Failing::default()
+        })
+        .circuit_breaker(2, Duration::from_secs(60))
+        .build()
+        .unwrap();
+
+        for _ in 0..2 {
+            assert!(matches!(limiter.count("key").await, Err(Error::Other(_))));
+        }
+        assert!(matches!(limiter.count("key").await, Err(Error::CircuitOpen)));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_storage_key() {
+        let limiter = Limiter::builder(Exhausted).build().unwrap();
+        assert_eq!(limiter.storage_key("ip:1"), "fixed_window:ip:1");
+
+        let limiter = Limiter::builder(Exhausted)
+            .key_prefix("rl")
+            .name("login")
+            .algorithm(Algorithm::Gcra)
+            .hash_keys_over(8)
+            .build()
+            .unwrap();
+        assert_eq!(limiter.storage_key("ip:1"), "rl:login:gcra:ip:1");
+        assert_eq!(
+            limiter.storage_key("a-long-token"),
+            format!(
+                "rl:login:gcra:{}",
+                sha1_smol::Sha1::from("a-long-token").digest()
+            )
+        );
+
+        let limiter = Limiter::builder(Exhausted).name("search").build().unwrap();
+        assert_eq!(limiter.storage_key("ip:1"), "search:fixed_window:ip:1");
+    }
+
+    #[actix_web::test]
+    async fn test_count_named_limiters_are_independent() {
+        let redis = FakeRedis::start();
+        let limiter = |name| {
+            Limiter::builder(redis.backend())
+                .limit(1)
+                .key_prefix("rl")
+                .name(name)
+                .build()
+                .unwrap()
+        };
+        let (login, search) = (limiter("login"), limiter("search"));
+
+        login.count("client").await.unwrap();
+        search.count("client").await.unwrap();
+        assert!(matches!(
+            login.count("client").await,
+            Err(Error::LimitExceeded(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_count_unnamed_limiters_with_different_algorithms() {
+        let redis = FakeRedis::start();
+        let limiter = |algorithm| {
+            Limiter::builder(redis.backend())
+                .limit(1)
+                .algorithm(algorithm)
+                .build()
+                .unwrap()
+        };
+        let (login, search) = (limiter(Algorithm::Gcra), limiter(Algorithm::SlidingWindow));
+
+        // each algorithm keeps its own counters, even with the same key and period
+        login.count("client").await.unwrap();
+        search.count("client").await.unwrap();
+        assert!(matches!(
+            login.count("client").await,
+            Err(Error::LimitExceeded(_))
+        ));
+        assert!(matches!(
+            search.count("client").await,
+            Err(Error::LimitExceeded(_))
+        ));
+    }
+
+    async fn assert_rule_sets_are_independent(backend: impl Backend) {
+        let limiter = Limiter::builder(backend).build().unwrap();
+        let basic = [Rule::new(1, Duration::from_secs(60))];
+        let pro = [Rule::new(1, Duration::from_secs(3600))];
+
+        limiter.count_rules("key", &basic, 1).await.unwrap();
+        limiter.count_rules("key", &pro, 1).await.unwrap();
+        assert!(matches!(
+            limiter.count_rules("key", &basic, 1).await,
+            Err(Error::LimitExceeded(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_rule_sets_with_different_periods_are_independent() {
+        let redis = FakeRedis::start();
+        assert_rule_sets_are_independent(redis.backend()).await;
+        assert_rule_sets_are_independent(crate::MemoryBackend::new()).await;
+    }
+
+    async fn assert_peek(backend: impl Backend + Clone) {
+        let algorithms = [
+            Algorithm::FixedWindow,
+            Algorithm::SlidingLog,
+            Algorithm::SlidingWindow,
+            Algorithm::TokenBucket { capacity: 3 },
+            Algorithm::Gcra,
+            Algorithm::LeakyBucket {
+                max_wait: Duration::from_secs(120),
+            },
+        ];
+
+        for algorithm in algorithms {
+            let limiter = Limiter::builder(backend.clone())
+                .limit(3)
+                .period(Duration::from_secs(180))
+                .algorithm(algorithm)
+                .build()
+                .unwrap();
+            let key = uuid::Uuid::new_v4().to_string();
+
+            assert_eq!(limiter.peek(&key).await.unwrap().remaining(), 3);
+            limiter.count(&key).await.unwrap();
+            for _ in 0..2 {
+                let status = limiter.peek(&key).await.unwrap();
+                assert_eq!(status.remaining(), 2, "{algorithm:?}");
+            }
+
+            limiter.count_n(&key, 2).await.unwrap();
+            let status = limiter.peek(&key).await.unwrap();
+            assert_eq!(status.remaining(), 0, "{algorithm:?}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_peek() {
+        let redis = FakeRedis::start();
+        assert_peek(redis.backend()).await;
+        assert_peek(crate::MemoryBackend::new()).await;
+    }
+}
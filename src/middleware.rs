@@ -8,12 +8,31 @@ use actix_web::{
     web, Error, HttpResponse,
 };
 
-use crate::{Error as LimitationError, Limiter};
+use crate::{Error as LimitationError, FailurePolicy, Limiter};
 
 /// Rate limit middleware.
+///
+/// [`RateLimiter::default()`] enforces the `web::Data<Limiter>` found in app data, while
+/// [`RateLimiter::new`] carries its own limiter, so that scopes and resources can be wrapped with
+/// different policies.
 #[derive(Debug, Default)]
 #[non_exhaustive]
-pub struct RateLimiter;
+pub struct RateLimiter {
+    limiter: Option<web::Data<Limiter>>,
+}
+
+impl RateLimiter {
+    /// Constructs a middleware enforcing `limiter`, regardless of the limiter in app data.
+    ///
+    /// Per-route limiters sharing a backend and algorithm must each set a distinct
+    /// [`name`](crate::Builder::name), or they count the same key against the same counters.
+    #[must_use]
+    pub fn new(limiter: Limiter) -> Self {
+        RateLimiter {
+            limiter: Some(web::Data::new(limiter)),
+        }
+    }
+}
 
 impl<S, B> Transform<S, ServiceRequest> for RateLimiter
 where
@@ -30,6 +49,7 @@ where
     fn new_transform(&self, service: S) -> Self::Future {
         ok(RateLimiterMiddleware {
             service: Rc::new(service),
+            limiter: self.limiter.clone(),
         })
     }
 }
@@ -38,6 +58,7 @@ where
 #[derive(Debug)]
 pub struct RateLimiterMiddleware<S> {
     service: Rc<S>,
+    limiter: Option<web::Data<Limiter>>,
 }
 
 impl<S, B> Service<ServiceRequest> for RateLimiterMiddleware<S>
@@ -55,61 +76,657 @@ where
     fn call(&self, req: ServiceRequest) -> Self::Future {
         // A misconfiguration of the Actix App will result in a **runtime** failure, so the expect
         // method description is important context for the developer.
-        let limiter = req
-            .app_data::<web::Data<Limiter>>()
-            .expect("web::Data<Limiter> should be set in app data for RateLimiter middleware")
-            .clone();
+        let limiter = match &self.limiter {
+            Some(limiter) => limiter.clone(),
+            None => req
+                .app_data::<web::Data<Limiter>>()
+                .expect("web::Data<Limiter> should be set in app data for RateLimiter middleware")
+                .clone(),
+        };
 
-        let key = (limiter.get_key_fn)(&req);
+        let rules = limiter.rules_for(&req);
+        let cost = limiter.cost_for(&req);
         let service = Rc::clone(&self.service);
 
-        let key = match key {
-            Some(key) => key,
-            None => {
-                return Box::pin(async move {
-                    service
+        Box::pin(async move {
+            if limiter.is_denied_client(&req) {
+                log::warn!("Denied request from a denied network");
+
+                return Ok(req.into_response(
+                    HttpResponse::new(StatusCode::FORBIDDEN).map_into_right_body(),
+                ));
+            }
+
+            let key = match limiter.key_for(&req).await {
+                Some(key) => key,
+                None => {
+                    return service
                         .call(req)
                         .await
-                        .map(ServiceResponse::map_into_left_body)
-                });
+                        .map(ServiceResponse::map_into_left_body);
+                }
+            };
+
+            if limiter.is_denied(&req, &key) {
+                log::warn!("Denied request for {}", key);
+
+                return Ok(req.into_response(
+                    HttpResponse::new(StatusCode::FORBIDDEN).map_into_right_body(),
+                ));
             }
-        };
 
-        Box::pin(async move {
-            let status = limiter.count(key.to_string()).await;
+            if limiter.is_allowed(&req, &key) {
+                return service
+                    .call(req)
+                    .await
+                    .map(ServiceResponse::map_into_left_body);
+            }
 
-            if let Err(err) = status {
-                match err {
-                    LimitationError::LimitExceeded(_) => {
-                        log::warn!("Rate limit exceed error for {}", key);
+            let rules = rules.as_deref().unwrap_or(&limiter.rules);
 
-                        Ok(req.into_response(
-                            HttpResponse::new(StatusCode::TOO_MANY_REQUESTS).map_into_right_body(),
-                        ))
+            match limiter.count_rules(&key, rules, cost).await {
+                Ok(status) => {
+                    if !status.delay().is_zero() {
+                        actix_web::rt::time::sleep(status.delay()).await;
                     }
-                    LimitationError::Pool(e) => {
-                        log::error!("Client request failed, redis error: {}", e);
 
-                        Ok(req.into_response(
-                            HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
-                                .map_into_right_body(),
-                        ))
-                    }
-                    _ => {
-                        log::error!("Count failed: {}", err);
+                    let mut res = service.call(req).await?;
+                    limiter
+                        .header_format
+                        .insert(res.headers_mut(), &status, rules[0].period());
 
-                        Ok(req.into_response(
-                            HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
-                                .map_into_right_body(),
-                        ))
-                    }
+                    Ok(res.map_into_left_body())
+                }
+                Err(LimitationError::LimitExceeded(status)) => {
+                    log::warn!("Rate limit exceed error for {}", key);
+
+                    let mut res = limiter.limit_exceeded_response(&req, &status);
+                    limiter
+                        .header_format
+                        .insert(res.headers_mut(), &status, rules[0].period());
+
+                    Ok(req.into_response(res.map_into_right_body()))
+                }
+                Err(err) if limiter.failure_policy == FailurePolicy::FailOpen => {
+                    log::warn!("Count failed, allowing request for {}: {}", key, err);
+
+                    service
+                        .call(req)
+                        .await
+                        .map(ServiceResponse::map_into_left_body)
+                }
+                Err(err) => {
+                    log::error!("Count failed, rejecting request for {}: {}", key, err);
+
+                    Ok(req.into_response(
+                        HttpResponse::new(StatusCode::SERVICE_UNAVAILABLE).map_into_right_body(),
+                    ))
                 }
-            } else {
-                service
-                    .call(req)
-                    .await
-                    .map(ServiceResponse::map_into_left_body)
             }
         })
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use actix_web::{test, App};
+
+    use super::*;
+    use crate::{
+        backend::BoxFuture,
+        keys::{self, ForwardedHeader, IpNet},
+        testing::FakeRedis,
+        Algorithm, Backend, HeaderFormat, Rule, Status,
+    };
+
+    /// Backend failing every hit, as when Redis is unreachable.
+    #[derive(Debug)]
+    struct Unavailable;
+
+    impl Backend for Unavailable {
+        fn hit<'a>(
+            &'a self,
+            _key: &'a str,
+            _algorithm: Algorithm,
+            _rules: &'a [Rule],
+            _cost: usize,
+        ) -> BoxFuture<'a, Result<Status, LimitationError>> {
+            Box::pin(async { Err(LimitationError::Other("unavailable".to_owned())) })
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_rejects_after_limit() {
+        let redis = FakeRedis::start();
+        let limiter = Limiter::builder(redis.backend())
+            .limit(3)
+            .period(Duration::from_secs(60))
+            .key_by(|_| Some("client".to_owned()))
+            .build()
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(limiter))
+                .wrap(RateLimiter::default())
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        for _ in 0..3 {
+            let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+
+        let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[actix_web::test]
+    async fn test_headers_on_allowed_and_rejected() {
+        let redis = FakeRedis::start();
+        let limiter = Limiter::builder(redis.backend())
+            .limit(1)
+            .period(Duration::from_secs(60))
+            .key_by(|_| Some("client".to_owned()))
+            .build()
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(limiter))
+                .wrap(RateLimiter::default())
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get("x-ratelimit-limit").unwrap(), "1");
+        assert_eq!(res.headers().get("x-ratelimit-remaining").unwrap(), "0");
+        assert!(res.headers().contains_key("x-ratelimit-reset"));
+        assert!(res.headers().contains_key("retry-after"));
+
+        let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers().get("x-ratelimit-limit").unwrap(), "1");
+        assert_eq!(res.headers().get("x-ratelimit-remaining").unwrap(), "0");
+        assert!(res.headers().contains_key("x-ratelimit-reset"));
+        assert!(res.headers().contains_key("retry-after"));
+    }
+
+    #[actix_web::test]
+    async fn test_draft_headers() {
+        let redis = FakeRedis::start();
+        let limiter = Limiter::builder(redis.backend())
+            .limit(5)
+            .period(Duration::from_secs(60))
+            .key_by(|_| Some("client".to_owned()))
+            .header_format(HeaderFormat::Draft)
+            .build()
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(limiter))
+                .wrap(RateLimiter::default())
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+        assert_eq!(
+            res.headers().get("ratelimit-policy").unwrap(),
+            "\"default\";q=5;w=60"
+        );
+        assert!(res.headers().contains_key("ratelimit"));
+        assert!(!res.headers().contains_key("x-ratelimit-limit"));
+    }
+
+    #[actix_web::test]
+    async fn test_headers_with_server_time() {
+        let redis = FakeRedis::start();
+        // the server clock runs an hour ahead of the application's
+        redis.skew_clock(3_600_000);
+        let limiter = Limiter::builder(redis.backend().with_server_time())
+            .limit(1)
+            .period(Duration::from_secs(60))
+            .key_by(|_| Some("client".to_owned()))
+            .header_format(HeaderFormat::Draft)
+            .build()
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .wrap(RateLimiter::new(limiter))
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get("ratelimit").unwrap(),
+            "\"default\";r=0;t=60"
+        );
+        assert_eq!(res.headers().get("retry-after").unwrap(), "60");
+
+        let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(
+            res.headers().get("ratelimit").unwrap(),
+            "\"default\";r=0;t=60"
+        );
+        assert_eq!(res.headers().get("retry-after").unwrap(), "60");
+    }
+
+    #[actix_web::test]
+    async fn test_passes_through_without_key() {
+        let redis = FakeRedis::start();
+        let limiter = Limiter::builder(redis.backend())
+            .limit(1)
+            .key_by(|_| None)
+            .build()
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(limiter))
+                .wrap(RateLimiter::default())
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        for _ in 0..3 {
+            let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_leaky_bucket_delays_requests() {
+        let limiter = Limiter::builder(crate::MemoryBackend::new())
+            .limit(10)
+            .period(Duration::from_secs(1))
+            .leaky_bucket(Duration::from_millis(150))
+            .key_by(|_| Some("client".to_owned()))
+            .build()
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(limiter))
+                .wrap(RateLimiter::default())
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let start = std::time::Instant::now();
+        for _ in 0..2 {
+            let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+        // the second request waited for its slot 100ms after the first
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[actix_web::test]
+    async fn test_per_scope_limiters() {
+        let limiter = |limit| {
+            Limiter::builder(crate::MemoryBackend::new())
+                .limit(limit)
+                .period(Duration::from_secs(60))
+                .key_by(|_| Some("client".to_owned()))
+                .build()
+                .unwrap()
+        };
+
+        let app = test::init_service(
+            App::new()
+                .service(
+                    web::scope("/login")
+                        .wrap(RateLimiter::new(limiter(1)))
+                        .route("", web::post().to(HttpResponse::Ok)),
+                )
+                .service(
+                    web::scope("/search")
+                        .wrap(RateLimiter::new(limiter(3)))
+                        .route("", web::get().to(HttpResponse::Ok)),
+                ),
+        )
+        .await;
+
+        let login = || test::TestRequest::post().uri("/login").to_request();
+        let search = || test::TestRequest::get().uri("/search").to_request();
+
+        assert_eq!(test::call_service(&app, login()).await.status(), StatusCode::OK);
+        assert_eq!(
+            test::call_service(&app, login()).await.status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+
+        for _ in 0..3 {
+            let res = test::call_service(&app, search()).await;
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(res.headers().get("x-ratelimit-limit").unwrap(), "3");
+        }
+        assert_eq!(
+            test::call_service(&app, search()).await.status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_limit_by() {
+        let limiter = Limiter::builder(crate::MemoryBackend::new())
+            .limit(1)
+            .period(Duration::from_secs(60))
+            .key_by(|req| Some(req.path().to_owned()))
+            .limit_by(|req| {
+                req.headers()
+                    .contains_key("x-pro")
+                    .then(|| vec![crate::Rule::new(3, Duration::from_secs(60))])
+            })
+            .build()
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .wrap(RateLimiter::new(limiter))
+                .default_service(web::to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let free = || test::TestRequest::get().uri("/free").to_request();
+        assert_eq!(test::call_service(&app, free()).await.status(), StatusCode::OK);
+        assert_eq!(
+            test::call_service(&app, free()).await.status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+
+        let pro = || {
+            test::TestRequest::get()
+                .uri("/pro")
+                .insert_header(("x-pro", "1"))
+                .to_request()
+        };
+        for _ in 0..3 {
+            let res = test::call_service(&app, pro()).await;
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(res.headers().get("x-ratelimit-limit").unwrap(), "3");
+        }
+        assert_eq!(
+            test::call_service(&app, pro()).await.status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_limit_by_invalid_rules() {
+        let limiter = Limiter::builder(crate::MemoryBackend::new())
+            .token_bucket(1, 1, Duration::from_secs(60))
+            .key_by(|_| Some("client".to_owned()))
+            .limit_by(|req| match req.path() {
+                "/empty" => Some(vec![]),
+                "/no-refill" => Some(vec![crate::Rule::new(0, Duration::from_secs(60))]),
+                _ => Some(vec![crate::Rule::new(5, Duration::ZERO)]),
+            })
+            .circuit_breaker(1, Duration::from_secs(60))
+            .build()
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .wrap(RateLimiter::new(limiter))
+                .default_service(web::to(HttpResponse::Ok)),
+        )
+        .await;
+
+        // invalid rules fall back to the builder's, without tripping the circuit breaker
+        let req = |path| test::TestRequest::get().uri(path).to_request();
+        let res = test::call_service(&app, req("/empty")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get("x-ratelimit-limit").unwrap(), "1");
+        for path in ["/empty", "/no-refill", "/zero-period"] {
+            let res = test::call_service(&app, req(path)).await;
+            assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS, "{path}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_key_by_async() {
+        let limiter = Limiter::builder(crate::MemoryBackend::new())
+            .limit(1)
+            .period(Duration::from_secs(60))
+            .key_by_async(|req| {
+                Box::pin(async move {
+                    actix_web::rt::task::yield_now().await;
+                    let api_key = req.headers().get("x-api-key")?.to_str().ok()?;
+                    Some(format!("tenant:{}", api_key.split('.').next()?))
+                })
+            })
+            .build()
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .wrap(RateLimiter::new(limiter))
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = |api_key| {
+            test::TestRequest::get()
+                .insert_header(("x-api-key", api_key))
+                .to_request()
+        };
+        assert_eq!(test::call_service(&app, req("a.1")).await.status(), StatusCode::OK);
+        // another key of the same tenant shares its quota
+        assert_eq!(
+            test::call_service(&app, req("a.2")).await.status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(test::call_service(&app, req("b.1")).await.status(), StatusCode::OK);
+
+        // requests without a resolved key are not limited
+        for _ in 0..3 {
+            let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_failure_policy() {
+        let app = |policy| {
+            let limiter = Limiter::builder(Unavailable)
+                .limit(1)
+                .key_by(|_| Some("client".to_owned()))
+                .failure_policy(policy)
+                .build()
+                .unwrap();
+
+            test::init_service(
+                App::new()
+                    .wrap(RateLimiter::new(limiter))
+                    .route("/", web::get().to(HttpResponse::Ok)),
+            )
+        };
+        let req = || test::TestRequest::get().to_request();
+
+        let closed = app(FailurePolicy::FailClosed).await;
+        let res = test::call_service(&closed, req()).await;
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let open = app(FailurePolicy::FailOpen).await;
+        for _ in 0..2 {
+            let res = test::call_service(&open, req()).await;
+            assert_eq!(res.status(), StatusCode::OK);
+            assert!(!res.headers().contains_key("x-ratelimit-limit"));
+        }
+
+        let fallback = app(FailurePolicy::Fallback).await;
+        let res = test::call_service(&fallback, req()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get("x-ratelimit-limit").unwrap(), "1");
+        let res = test::call_service(&fallback, req()).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[actix_web::test]
+    async fn test_limit_exceeded_response() {
+        let app = |limiter| {
+            test::init_service(
+                App::new()
+                    .wrap(RateLimiter::new(limiter))
+                    .route("/", web::get().to(HttpResponse::Ok)),
+            )
+        };
+        let builder = || {
+            let mut builder = Limiter::builder(crate::MemoryBackend::new());
+            builder.limit(1).key_by(|_| Some("client".to_owned()));
+            builder
+        };
+        let req = || test::TestRequest::get().to_request();
+
+        let default = app(builder().build().unwrap()).await;
+        test::call_service(&default, req()).await;
+        let res = test::call_service(&default, req()).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(
+            res.headers().get("content-type").unwrap(),
+            "application/problem+json"
+        );
+        let body = test::read_body(res).await;
+        assert!(body.starts_with(br#"{"type":"about:blank","title":"Too Many Requests""#));
+
+        let limiter = builder()
+            .on_limit_exceeded(|req, status| {
+                let body = format!("{} {}", req.path(), status.limit());
+                HttpResponse::ServiceUnavailable().body(body)
+            })
+            .build()
+            .unwrap();
+        let custom = app(limiter).await;
+        test::call_service(&custom, req()).await;
+        let res = test::call_service(&custom, req()).await;
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers().get("x-ratelimit-limit").unwrap(), "1");
+        assert_eq!(test::read_body(res).await, "/ 1");
+    }
+
+    #[actix_web::test]
+    async fn test_allow_and_deny_lists() {
+        let limiter = Limiter::builder(crate::MemoryBackend::new())
+            .limit(1)
+            .key_by(crate::keys::header("x-api-key"))
+            .allow_if(|req, _| req.path() == "/health")
+            .allow_networks(["192.0.2.0/24".parse().unwrap()])
+            .deny_keys(["stolen"])
+            .deny_networks(["198.51.100.0/24".parse().unwrap()])
+            .build()
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .wrap(RateLimiter::new(limiter))
+                .default_service(web::to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = |peer: &str, path, api_key: Option<&str>| {
+            let req = test::TestRequest::get()
+                .uri(path)
+                .peer_addr(format!("{peer}:1234").parse().unwrap());
+            match api_key {
+                Some(api_key) => req.insert_header(("x-api-key", api_key)).to_request(),
+                None => req.to_request(),
+            }
+        };
+
+        for _ in 0..3 {
+            let res = test::call_service(&app, req("10.0.0.1", "/health", Some("a"))).await;
+            assert_eq!(res.status(), StatusCode::OK);
+            assert!(!res.headers().contains_key("x-ratelimit-limit"));
+
+            // networks match the client address, whatever the key
+            let res = test::call_service(&app, req("192.0.2.10", "/", Some("b"))).await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+
+        let res = test::call_service(&app, req("10.0.0.1", "/", Some("stolen"))).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let res = test::call_service(&app, req("198.51.100.1", "/health", Some("a"))).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        // denied networks are rejected before resolving the key
+        let res = test::call_service(&app, req("198.51.100.1", "/", None)).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let res = test::call_service(&app, req("10.0.0.1", "/", Some("c"))).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = test::call_service(&app, req("10.0.0.1", "/", Some("c"))).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[actix_web::test]
+    async fn test_client_ip_by() {
+        let trusted: [IpNet; 1] = ["10.0.0.0/8".parse().unwrap()];
+        let limiter = Limiter::builder(crate::MemoryBackend::new())
+            .key_by(|_| Some("client".to_owned()))
+            .client_ip_by(keys::real_ip(ForwardedHeader::XForwardedFor, trusted))
+            .deny_networks(["198.51.100.0/24".parse().unwrap()])
+            .build()
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .wrap(RateLimiter::new(limiter))
+                .default_service(web::to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .peer_addr("10.0.0.1:1234".parse().unwrap())
+            .insert_header(("x-forwarded-for", "198.51.100.1"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::get()
+            .peer_addr("10.0.0.1:1234".parse().unwrap())
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_cost_by() {
+        let limiter = Limiter::builder(crate::MemoryBackend::new())
+            .limit(10)
+            .key_by(|_| Some("client".to_owned()))
+            .cost_by(|req| if req.path() == "/bulk" { 4 } else { 1 })
+            .build()
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .wrap(RateLimiter::new(limiter))
+                .default_service(web::to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = |path| test::TestRequest::get().uri(path).to_request();
+        for remaining in ["6", "2"] {
+            let res = test::call_service(&app, req("/bulk")).await;
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(res.headers().get("x-ratelimit-remaining").unwrap(), remaining);
+        }
+        let res = test::call_service(&app, req("/bulk")).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let res = test::call_service(&app, req("/")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get("x-ratelimit-remaining").unwrap(), "1");
+    }
+}
@@ -0,0 +1,55 @@
+use actix_web::{HttpResponse, http::StatusCode};
+
+use crate::{headers::retry_after_secs, status::Status};
+
+/// Media type of RFC 9457 problem details.
+const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Builds the default response to rejected requests: `429 Too Many Requests` with an
+/// [RFC 9457](https://www.rfc-editor.org/rfc/rfc9457) problem details body.
+pub(crate) fn problem_details(status: &Status) -> HttpResponse {
+    let retry_after = retry_after_secs(status);
+    // all fields are numbers or fixed strings, so no JSON escaping is needed
+    let body = format!(
+        concat!(
+            "{{\"type\":\"about:blank\",\"title\":\"Too Many Requests\",\"status\":429,",
+            "\"detail\":\"Rate limit exceeded, retry in {retry_after} seconds\",",
+            "\"limit\":{limit},\"remaining\":{remaining},\"retry_after\":{retry_after}}}"
+        ),
+        limit = status.limit(),
+        remaining = status.remaining(),
+        retry_after = retry_after,
+    );
+
+    HttpResponse::build(StatusCode::TOO_MANY_REQUESTS)
+        .content_type(PROBLEM_JSON)
+        .body(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use actix_web::{body::MessageBody as _, http::header::CONTENT_TYPE};
+
+    use super::*;
+
+    #[test]
+    fn test_problem_details() {
+        let status = Status::new(11, 10, 0).with_retry_after(Duration::from_millis(1500));
+        let res = problem_details(&status);
+
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), PROBLEM_JSON);
+
+        let body = res.into_body().try_into_bytes().unwrap();
+        assert_eq!(
+            body,
+            concat!(
+                r#"{"type":"about:blank","title":"Too Many Requests","status":429,"#,
+                r#""detail":"Rate limit exceeded, retry in 2 seconds","#,
+                r#""limit":10,"remaining":0,"retry_after":2}"#
+            )
+        );
+    }
+}
@@ -0,0 +1,31 @@
+use std::time::Duration;
+
+/// A quota of `limit` requests per `period`.
+///
+/// A [`Limiter`](crate::Limiter) enforces one or more rules together; a request is only allowed
+/// when every rule allows it. See [`Builder::rule`](crate::Builder::rule).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rule {
+    limit: usize,
+    period: Duration,
+}
+
+impl Rule {
+    /// Constructs a rule allowing `limit` requests per `period`.
+    #[must_use]
+    pub const fn new(limit: usize, period: Duration) -> Self {
+        Rule { limit, period }
+    }
+
+    /// Returns the number of requests allowed per period.
+    #[must_use]
+    pub const fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Returns the period over which the limit applies.
+    #[must_use]
+    pub const fn period(&self) -> Duration {
+        self.period
+    }
+}
@@ -10,21 +10,67 @@ pub struct Status {
     pub(crate) limit: usize,
     pub(crate) remaining: usize,
     pub(crate) reset_epoch_utc: usize,
+    pub(crate) reset_epoch_utc_ms: u64,
+    pub(crate) reset_after: Option<Duration>,
+    pub(crate) period: Option<Duration>,
+    pub(crate) retry_after: Option<Duration>,
+    pub(crate) delay: Duration,
 }
 
 impl Status {
     /// Constructs status limit status from parts.
     #[must_use]
-    pub(crate) fn new(count: usize, limit: usize, reset_epoch_utc: usize) -> Self {
+    pub fn new(count: usize, limit: usize, reset_epoch_utc: usize) -> Self {
         let remaining = limit.saturating_sub(count);
 
         Status {
             limit,
             remaining,
             reset_epoch_utc,
+            reset_epoch_utc_ms: reset_epoch_utc as u64 * 1000,
+            reset_after: None,
+            period: None,
+            retry_after: None,
+            delay: Duration::ZERO,
         }
     }
 
+    /// Sets the millisecond UNIX timestamp of the reset, for periods that do not end on a whole
+    /// second.
+    #[must_use]
+    pub fn with_reset_epoch_utc_ms(mut self, reset_epoch_utc_ms: u64) -> Self {
+        self.reset_epoch_utc_ms = reset_epoch_utc_ms;
+        self
+    }
+
+    /// Sets how long until the next period begins, as measured by the clock that timed the reset.
+    #[must_use]
+    pub fn with_reset_after(mut self, reset_after: Duration) -> Self {
+        self.reset_after = Some(reset_after);
+        self
+    }
+
+    /// Sets the period of the rule this status reports.
+    #[must_use]
+    pub fn with_period(mut self, period: Duration) -> Self {
+        self.period = Some(period);
+        self
+    }
+
+    /// Sets how long a rejected key has to wait before its next request is allowed.
+    #[must_use]
+    pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(retry_after);
+        self
+    }
+
+    /// Sets how long an allowed request has to wait for its slot before being handled.
+    #[must_use]
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
     /// Returns the maximum number of requests allowed in the current period.
     #[must_use]
     pub fn limit(&self) -> usize {
@@ -43,6 +89,42 @@ impl Status {
         self.reset_epoch_utc
     }
 
+    /// Returns a UNIX timestamp in UTC, in milliseconds, when the next period will begin.
+    #[must_use]
+    pub fn reset_epoch_utc_ms(&self) -> u64 {
+        self.reset_epoch_utc_ms
+    }
+
+    /// Returns how long until the next period begins, if known.
+    ///
+    /// Unlike the reset timestamps, this does not depend on the clock of the application matching
+    /// the clock of the backend.
+    #[must_use]
+    pub fn reset_after(&self) -> Option<Duration> {
+        self.reset_after
+    }
+
+    /// Returns the period of the reported rule, i.e. the most restrictive one when a limiter has
+    /// several rules, if known.
+    #[must_use]
+    pub fn period(&self) -> Option<Duration> {
+        self.period
+    }
+
+    /// Returns how long to wait before the next request is allowed, if the key was rejected.
+    #[must_use]
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.retry_after
+    }
+
+    /// Returns how long the request should be delayed before being handled.
+    ///
+    /// Always zero, except for [`Algorithm::LeakyBucket`](crate::Algorithm::LeakyBucket).
+    #[must_use]
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
     pub(crate) fn epoch_utc_plus(duration: Duration) -> Result<usize, LimitationError> {
         match chrono::Duration::from_std(duration) {
             Ok(value) => Ok(chrono::Utc::now()
@@ -57,6 +139,28 @@ impl Status {
             )),
         }
     }
+
+    /// Returns the UNIX timestamps, in whole seconds and in milliseconds, `duration` after the
+    /// millisecond UNIX timestamp `now`.
+    pub(crate) fn epoch_utc_at_plus(now: u64, duration: Duration) -> (usize, u64) {
+        let millis = u64::try_from(duration.as_millis()).unwrap_or(u64::MAX);
+        let reset = now.saturating_add(millis);
+        ((reset.saturating_add(500) / 1000) as usize, reset)
+    }
+
+    pub(crate) fn epoch_utc_ms_plus(duration: Duration) -> Result<u64, LimitationError> {
+        match chrono::Duration::from_std(duration) {
+            Ok(value) => Ok(chrono::Utc::now()
+                .add(value)
+                .timestamp_millis()
+                .try_into()
+                .unwrap_or(0)),
+
+            Err(_) => Err(LimitationError::Other(
+                "Source duration value is out of range for the target type".to_string(),
+            )),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -69,11 +173,28 @@ mod tests {
             limit: 100,
             remaining: 0,
             reset_epoch_utc: 1000,
+            reset_epoch_utc_ms: 1_000_250,
+            reset_after: None,
+            period: None,
+            retry_after: None,
+            delay: Duration::ZERO,
         };
 
         assert_eq!(status.limit(), 100);
         assert_eq!(status.remaining(), 0);
         assert_eq!(status.reset_epoch_utc(), 1000);
+        assert_eq!(status.reset_epoch_utc_ms(), 1_000_250);
+        assert_eq!(status.reset_after(), None);
+        assert_eq!(status.period(), None);
+        assert_eq!(status.retry_after(), None);
+        assert_eq!(status.delay(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_status_retry_after() {
+        let status = Status::new(101, 100, 2000).with_retry_after(Duration::from_millis(250));
+        assert_eq!(status.remaining(), 0);
+        assert_eq!(status.retry_after(), Some(Duration::from_millis(250)));
     }
 
     #[test]
@@ -84,6 +205,7 @@ mod tests {
         assert_eq!(status.limit(), limit);
         assert_eq!(status.remaining(), 0);
         assert_eq!(status.reset_epoch_utc(), 2000);
+        assert_eq!(status.reset_epoch_utc_ms(), 2_000_000);
     }
 
     #[test]
@@ -109,6 +231,20 @@ mod tests {
         assert!(seconds as u64 >= duration.as_secs() + 10);
     }
 
+    #[test]
+    fn test_epoch_utc_ms_plus() {
+        let duration = Duration::from_millis(500);
+        let seconds = Status::epoch_utc_plus(Duration::ZERO).unwrap() as u64;
+        let millis = Status::epoch_utc_ms_plus(duration).unwrap();
+        assert!(millis.abs_diff(seconds * 1000 + 500) <= 1000);
+    }
+
+    #[test]
+    fn test_epoch_utc_at_plus() {
+        let reset = Status::epoch_utc_at_plus(1_000_000, Duration::from_millis(1_600));
+        assert_eq!(reset, (1_002, 1_001_600));
+    }
+
     #[test]
     #[should_panic = "Source duration value is out of range for the target type"]
     fn test_epoch_utc_plus_overflow() {
@@ -0,0 +1,599 @@
+//! Test support: a minimal in-process Redis stand-in.
+//!
+//! Speaks just enough RESP2 for `redis-rs` and `deadpool-redis`, and runs `EVAL`/`EVALSHA` scripts
+//! through an embedded Lua 5.1 interpreter against an in-memory keyspace, so the limiter's scripts
+//! can be exercised without a Redis server.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use deadpool_redis::{Config, Runtime};
+use mlua::{Lua, MultiValue, Value as LuaValue};
+
+use crate::RedisBackend;
+
+/// A running Redis stand-in listening on a random local port.
+#[derive(Debug)]
+pub(crate) struct FakeRedis {
+    addr: SocketAddr,
+    keyspace: Arc<Mutex<Keyspace>>,
+}
+
+impl FakeRedis {
+    /// Starts the stand-in on a background thread.
+    pub(crate) fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let keyspace = Arc::new(Mutex::new(Keyspace::default()));
+
+        let shared = Arc::clone(&keyspace);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { break };
+                let keyspace = Arc::clone(&shared);
+                thread::spawn(move || serve(stream, keyspace));
+            }
+        });
+
+        FakeRedis { addr, keyspace }
+    }
+
+    /// Forgets all loaded scripts, as after a Redis restart or failover.
+    pub(crate) fn flush_scripts(&self) {
+        self.keyspace.lock().unwrap().scripts.clear();
+    }
+
+    /// Shifts the time reported by `TIME` by `offset_ms`, as on a server with a skewed clock.
+    pub(crate) fn skew_clock(&self, offset_ms: i64) {
+        self.keyspace.lock().unwrap().clock_skew_ms = offset_ms;
+    }
+
+    /// Returns the number of scripts loaded with `SCRIPT LOAD`.
+    pub(crate) fn scripts_loaded(&self) -> usize {
+        self.keyspace.lock().unwrap().loads
+    }
+
+    /// Creates a Redis backend connected to the stand-in.
+    pub(crate) fn backend(&self) -> RedisBackend {
+        let pool = Config::from_url(format!("redis://{}", self.addr))
+            .create_pool(Some(Runtime::Tokio1))
+            .unwrap();
+        RedisBackend::new(Arc::new(pool))
+    }
+}
+
+/// A RESP2 reply.
+#[derive(Debug)]
+enum Reply {
+    Status(String),
+    Error(String),
+    Int(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        match self {
+            Reply::Status(s) => write!(out, "+{s}\r\n"),
+            Reply::Error(e) => write!(out, "-{e}\r\n"),
+            Reply::Int(i) => write!(out, ":{i}\r\n"),
+            Reply::Bulk(None) => write!(out, "$-1\r\n"),
+            Reply::Bulk(Some(b)) => {
+                write!(out, "${}\r\n", b.len())?;
+                out.write_all(b)?;
+                write!(out, "\r\n")
+            }
+            Reply::Array(items) => {
+                write!(out, "*{}\r\n", items.len())?;
+                items.iter().try_for_each(|item| item.write_to(out))
+            }
+        }
+    }
+}
+
+/// Sorted set members ordered by `(score, member)`.
+type SortedSet = Vec<(f64, Vec<u8>)>;
+
+#[derive(Debug)]
+enum Value {
+    Str(Vec<u8>),
+    ZSet(SortedSet),
+    Hash(HashMap<Vec<u8>, Vec<u8>>),
+}
+
+#[derive(Debug)]
+struct Entry {
+    value: Value,
+    expires_at_ms: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct Keyspace {
+    entries: HashMap<Vec<u8>, Entry>,
+    /// Script sources by SHA1 hex digest.
+    scripts: HashMap<String, Vec<u8>>,
+    loads: usize,
+    /// Offset of the `TIME` clock from the local one.
+    clock_skew_ms: i64,
+}
+
+const WRONGTYPE: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+fn parse_int(arg: &[u8]) -> Result<i64, String> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| "ERR value is not an integer or out of range".to_owned())
+}
+
+fn parse_float(arg: &[u8]) -> Result<f64, String> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| "ERR value is not a valid float".to_owned())
+}
+
+/// Parses a sorted set range bound, returning the score and whether it is exclusive.
+fn parse_bound(arg: &[u8]) -> Result<(f64, bool), String> {
+    match arg {
+        b"-inf" => Ok((f64::NEG_INFINITY, false)),
+        b"+inf" | b"inf" => Ok((f64::INFINITY, false)),
+        [b'(', rest @ ..] => Ok((parse_float(rest)?, true)),
+        _ => Ok((parse_float(arg)?, false)),
+    }
+}
+
+fn format_float(value: f64) -> Vec<u8> {
+    value.to_string().into_bytes()
+}
+
+impl Keyspace {
+    /// Returns the live entry for `key`, dropping it first if it has expired.
+    fn live(&mut self, key: &[u8]) -> Option<&mut Entry> {
+        let now = now_ms();
+        if self
+            .entries
+            .get(key)
+            .is_some_and(|e| e.expires_at_ms.is_some_and(|at| at <= now))
+        {
+            self.entries.remove(key);
+        }
+        self.entries.get_mut(key)
+    }
+
+    /// Returns the live entry for `key`, creating it with `value` if missing.
+    fn live_or_insert(&mut self, key: &[u8], value: Value) -> &mut Entry {
+        if self.live(key).is_none() {
+            self.entries.insert(
+                key.to_vec(),
+                Entry {
+                    value,
+                    expires_at_ms: None,
+                },
+            );
+        }
+        self.entries.get_mut(key).unwrap()
+    }
+
+    fn zset(&mut self, key: &[u8]) -> Result<Option<&mut SortedSet>, String> {
+        match self.live(key).map(|e| &mut e.value) {
+            Some(Value::ZSet(set)) => Ok(Some(set)),
+            Some(_) => Err(WRONGTYPE.to_owned()),
+            None => Ok(None),
+        }
+    }
+
+    fn incr_by(&mut self, key: &[u8], by: i64) -> Result<Reply, String> {
+        let entry = self.live_or_insert(key, Value::Str(b"0".to_vec()));
+        let Value::Str(value) = &mut entry.value else {
+            return Err(WRONGTYPE.to_owned());
+        };
+        let next = parse_int(value)? + by;
+        *value = next.to_string().into_bytes();
+        Ok(Reply::Int(next))
+    }
+
+    fn expire_in(&mut self, key: &[u8], ms: i64) -> Reply {
+        Reply::Int(match self.live(key) {
+            Some(entry) => {
+                entry.expires_at_ms = Some(now_ms().saturating_add_signed(ms));
+                1
+            }
+            None => 0,
+        })
+    }
+
+    fn ttl_ms(&mut self, key: &[u8]) -> Option<i64> {
+        self.live(key).map(|e| {
+            e.expires_at_ms
+                .map_or(-1, |at| at.saturating_sub(now_ms()) as i64)
+        })
+    }
+
+    fn exec(&mut self, args: &[Vec<u8>]) -> Result<Reply, String> {
+        let Some((name, args)) = args.split_first() else {
+            return Err("ERR empty command".to_owned());
+        };
+        let name = String::from_utf8_lossy(name).to_uppercase();
+
+        match (name.as_str(), args) {
+            ("GET", [key]) => match self.live(key).map(|e| &e.value) {
+                Some(Value::Str(value)) => Ok(Reply::Bulk(Some(value.clone()))),
+                Some(_) => Err(WRONGTYPE.to_owned()),
+                None => Ok(Reply::Bulk(None)),
+            },
+
+            ("SET", [key, value, opts @ ..]) => {
+                let expires_at_ms = match opts {
+                    [] => None,
+                    [px, ms] if px.eq_ignore_ascii_case(b"PX") => {
+                        Some(now_ms().saturating_add_signed(parse_int(ms)?))
+                    }
+                    _ => return Err("ERR syntax error".to_owned()),
+                };
+                self.entries.insert(
+                    key.clone(),
+                    Entry {
+                        value: Value::Str(value.clone()),
+                        expires_at_ms,
+                    },
+                );
+                Ok(Reply::Status("OK".to_owned()))
+            }
+
+            ("DEL", keys) => {
+                let removed = keys
+                    .iter()
+                    .filter(|key| self.live(key).is_some() && self.entries.remove(*key).is_some())
+                    .count();
+                Ok(Reply::Int(removed as i64))
+            }
+
+            ("INCR", [key]) => self.incr_by(key, 1),
+            ("INCRBY", [key, by]) => self.incr_by(key, parse_int(by)?),
+
+            ("EXPIRE", [key, secs]) => Ok(self.expire_in(key, parse_int(secs)? * 1000)),
+            ("PEXPIRE", [key, ms]) => Ok(self.expire_in(key, parse_int(ms)?)),
+
+            ("TTL", [key]) => Ok(Reply::Int(match self.ttl_ms(key) {
+                Some(ms) if ms >= 0 => (ms + 500) / 1000,
+                Some(_) => -1,
+                None => -2,
+            })),
+            ("PTTL", [key]) => Ok(Reply::Int(self.ttl_ms(key).unwrap_or(-2))),
+
+            ("TIME", []) => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+                let micros = now.as_micros() as i64 + self.clock_skew_ms * 1000;
+                Ok(Reply::Array(vec![
+                    Reply::Bulk(Some((micros / 1_000_000).to_string().into_bytes())),
+                    Reply::Bulk(Some((micros % 1_000_000).to_string().into_bytes())),
+                ]))
+            }
+
+            ("ZADD", [key, score, member]) => {
+                let score = parse_float(score)?;
+                let entry = self.live_or_insert(key, Value::ZSet(Vec::new()));
+                let Value::ZSet(set) = &mut entry.value else {
+                    return Err(WRONGTYPE.to_owned());
+                };
+
+                let existed = set.iter().position(|(_, m)| m == member);
+                if let Some(idx) = existed {
+                    set.remove(idx);
+                }
+                let idx = set.partition_point(|(s, m)| (*s, m) < (score, member));
+                set.insert(idx, (score, member.clone()));
+
+                Ok(Reply::Int(if existed.is_some() { 0 } else { 1 }))
+            }
+
+            ("ZREMRANGEBYSCORE", [key, min, max]) => {
+                let (min, min_excl) = parse_bound(min)?;
+                let (max, max_excl) = parse_bound(max)?;
+                let in_range = |s: f64| {
+                    (if min_excl { s > min } else { s >= min })
+                        && (if max_excl { s < max } else { s <= max })
+                };
+
+                let Some(set) = self.zset(key)? else {
+                    return Ok(Reply::Int(0));
+                };
+                let before = set.len();
+                set.retain(|(s, _)| !in_range(*s));
+                Ok(Reply::Int((before - set.len()) as i64))
+            }
+
+            ("HMGET", [key, fields @ ..]) if !fields.is_empty() => {
+                let hash = match self.live(key).map(|e| &e.value) {
+                    Some(Value::Hash(hash)) => Some(hash),
+                    Some(_) => return Err(WRONGTYPE.to_owned()),
+                    None => None,
+                };
+                Ok(Reply::Array(
+                    fields
+                        .iter()
+                        .map(|f| Reply::Bulk(hash.and_then(|h| h.get(f)).cloned()))
+                        .collect(),
+                ))
+            }
+
+            ("HSET", [key, pairs @ ..]) if !pairs.is_empty() && pairs.len() % 2 == 0 => {
+                let entry = self.live_or_insert(key, Value::Hash(HashMap::new()));
+                let Value::Hash(hash) = &mut entry.value else {
+                    return Err(WRONGTYPE.to_owned());
+                };
+                let added = pairs
+                    .chunks(2)
+                    .filter(|pair| hash.insert(pair[0].clone(), pair[1].clone()).is_none())
+                    .count();
+                Ok(Reply::Int(added as i64))
+            }
+
+            ("ZCARD", [key]) => Ok(Reply::Int(self.zset(key)?.map_or(0, |s| s.len()) as i64)),
+
+            ("ZRANGE", [key, start, stop, rest @ ..]) => {
+                let with_scores = match rest {
+                    [] => false,
+                    [opt] if opt.eq_ignore_ascii_case(b"WITHSCORES") => true,
+                    _ => return Err("ERR syntax error".to_owned()),
+                };
+                let (start, stop) = (parse_int(start)?, parse_int(stop)?);
+
+                let set = self.zset(key)?.map(|s| s.as_slice()).unwrap_or_default();
+                let len = set.len() as i64;
+                let start = if start < 0 {
+                    (len + start).max(0)
+                } else {
+                    start
+                };
+                let stop = if stop < 0 {
+                    len + stop
+                } else {
+                    stop.min(len - 1)
+                };
+
+                let mut items = Vec::new();
+                for (score, member) in set
+                    .iter()
+                    .take((stop + 1).max(0) as usize)
+                    .skip(start as usize)
+                {
+                    items.push(Reply::Bulk(Some(member.clone())));
+                    if with_scores {
+                        items.push(Reply::Bulk(Some(format_float(*score))));
+                    }
+                }
+                Ok(Reply::Array(items))
+            }
+
+            _ => Err(format!("ERR unknown command or wrong arity '{name}'")),
+        }
+    }
+}
+
+/// Reads one RESP array of bulk strings, or `None` on EOF.
+fn read_command(reader: &mut impl BufRead) -> io::Result<Option<Vec<Vec<u8>>>> {
+    fn read_line(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.trim_end().to_owned()))
+    }
+
+    fn invalid(msg: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, msg)
+    }
+
+    let Some(header) = read_line(reader)? else {
+        return Ok(None);
+    };
+    let len: usize = header
+        .strip_prefix('*')
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(|| invalid("expected array"))?;
+
+    let mut args = Vec::with_capacity(len);
+    for _ in 0..len {
+        let size: usize = read_line(reader)?
+            .as_deref()
+            .and_then(|l| l.strip_prefix('$'))
+            .and_then(|n| n.parse().ok())
+            .ok_or_else(|| invalid("expected bulk string"))?;
+        let mut buf = vec![0; size + 2];
+        reader.read_exact(&mut buf)?;
+        buf.truncate(size);
+        args.push(buf);
+    }
+
+    Ok(Some(args))
+}
+
+fn serve(stream: TcpStream, keyspace: Arc<Mutex<Keyspace>>) {
+    let lua = Lua::new();
+    stream.set_nodelay(true).unwrap();
+    let mut writer = BufWriter::new(stream.try_clone().unwrap());
+    let mut reader = BufReader::new(stream);
+
+    while let Ok(Some(args)) = read_command(&mut reader) {
+        let name = args
+            .first()
+            .map(|n| String::from_utf8_lossy(n).to_uppercase())
+            .unwrap_or_default();
+
+        let reply = match name.as_str() {
+            "PING" => Reply::Status("PONG".to_owned()),
+            "CLIENT" | "SELECT" => Reply::Status("OK".to_owned()),
+            "EVAL" => eval(&lua, &mut keyspace.lock().unwrap(), &args[1..]),
+            "EVALSHA" => {
+                let mut keyspace = keyspace.lock().unwrap();
+                let script = args
+                    .get(1)
+                    .and_then(|sha| keyspace.scripts.get(&*String::from_utf8_lossy(sha)))
+                    .cloned();
+
+                match script {
+                    Some(script) => {
+                        let mut args = args[1..].to_vec();
+                        args[0] = script;
+                        eval(&lua, &mut keyspace, &args)
+                    }
+                    None => Reply::Error("NOSCRIPT No matching script.".to_owned()),
+                }
+            }
+            "SCRIPT" => match args.get(1).map(|sub| sub.to_ascii_uppercase()).as_deref() {
+                Some(b"LOAD") if args.len() == 3 => {
+                    let sha = sha1_smol::Sha1::from(&args[2]).digest().to_string();
+                    let mut keyspace = keyspace.lock().unwrap();
+                    keyspace.scripts.insert(sha.clone(), args[2].clone());
+                    keyspace.loads += 1;
+                    Reply::Bulk(Some(sha.into_bytes()))
+                }
+                Some(b"FLUSH") => {
+                    keyspace.lock().unwrap().scripts.clear();
+                    Reply::Status("OK".to_owned())
+                }
+                _ => Reply::Error("ERR unknown SCRIPT subcommand".to_owned()),
+            },
+            _ => keyspace
+                .lock()
+                .unwrap()
+                .exec(&args)
+                .unwrap_or_else(Reply::Error),
+        };
+
+        if reply
+            .write_to(&mut writer)
+            .and_then(|()| writer.flush())
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+/// Runs `EVAL script numkeys key... arg...` atomically against the keyspace.
+fn eval(lua: &Lua, keyspace: &mut Keyspace, args: &[Vec<u8>]) -> Reply {
+    let Some((script, rest)) = args.split_first() else {
+        return Reply::Error("ERR wrong number of arguments for 'eval'".to_owned());
+    };
+    let Some(numkeys) = rest
+        .first()
+        .and_then(|n| std::str::from_utf8(n).ok()?.parse::<usize>().ok())
+        .filter(|n| *n < rest.len())
+    else {
+        return Reply::Error("ERR Number of keys can't be greater than number of args".to_owned());
+    };
+    let (keys, argv) = rest[1..].split_at(numkeys);
+
+    let res = lua.scope(|scope| {
+        let globals = lua.globals();
+        globals.set("KEYS", strings(lua, keys)?)?;
+        globals.set("ARGV", strings(lua, argv)?)?;
+
+        let call = scope.create_function_mut(|lua, args: MultiValue| {
+            let args = args
+                .into_iter()
+                .map(|arg| match arg {
+                    LuaValue::String(s) => Ok(s.as_bytes().to_vec()),
+                    LuaValue::Integer(i) => Ok(i.to_string().into_bytes()),
+                    LuaValue::Number(n) if n.fract() == 0.0 => {
+                        Ok((n as i64).to_string().into_bytes())
+                    }
+                    LuaValue::Number(n) => Ok(n.to_string().into_bytes()),
+                    _ => Err(mlua::Error::runtime(
+                        "Lua redis() command arguments must be strings or integers",
+                    )),
+                })
+                .collect::<mlua::Result<Vec<_>>>()?;
+
+            let reply = keyspace.exec(&args).map_err(mlua::Error::runtime)?;
+            to_lua(lua, reply)
+        })?;
+
+        let redis = lua.create_table()?;
+        redis.set("call", call)?;
+        globals.set("redis", redis)?;
+
+        let value = lua.load(script.as_slice()).eval::<LuaValue>()?;
+        from_lua(value)
+    });
+
+    res.unwrap_or_else(|err| Reply::Error(format!("ERR {err}")))
+}
+
+fn strings<'lua>(lua: &'lua Lua, items: &[Vec<u8>]) -> mlua::Result<mlua::Table<'lua>> {
+    lua.create_sequence_from(
+        items
+            .iter()
+            .map(|item| lua.create_string(item))
+            .collect::<mlua::Result<Vec<_>>>()?,
+    )
+}
+
+/// Converts a Redis reply into a Lua value the way Redis does for `redis.call`.
+fn to_lua(lua: &Lua, reply: Reply) -> mlua::Result<LuaValue<'_>> {
+    Ok(match reply {
+        Reply::Int(i) => LuaValue::Number(i as f64),
+        Reply::Bulk(Some(b)) => LuaValue::String(lua.create_string(b)?),
+        Reply::Bulk(None) => LuaValue::Boolean(false),
+        Reply::Status(s) => {
+            let t = lua.create_table()?;
+            t.set("ok", s)?;
+            LuaValue::Table(t)
+        }
+        Reply::Error(e) => return Err(mlua::Error::runtime(e)),
+        Reply::Array(items) => LuaValue::Table(
+            lua.create_sequence_from(
+                items
+                    .into_iter()
+                    .map(|item| to_lua(lua, item))
+                    .collect::<mlua::Result<Vec<_>>>()?,
+            )?,
+        ),
+    })
+}
+
+/// Converts a script's return value into a Redis reply the way Redis does.
+fn from_lua(value: LuaValue<'_>) -> mlua::Result<Reply> {
+    Ok(match value {
+        LuaValue::Nil | LuaValue::Boolean(false) => Reply::Bulk(None),
+        LuaValue::Boolean(true) => Reply::Int(1),
+        LuaValue::Integer(i) => Reply::Int(i),
+        LuaValue::Number(n) => Reply::Int(n as i64),
+        LuaValue::String(s) => Reply::Bulk(Some(s.as_bytes().to_vec())),
+        LuaValue::Table(t) => {
+            if let Some(err) = t.get::<_, Option<String>>("err")? {
+                Reply::Error(err)
+            } else if let Some(ok) = t.get::<_, Option<String>>("ok")? {
+                Reply::Status(ok)
+            } else {
+                Reply::Array(
+                    t.sequence_values::<LuaValue>()
+                        .map(|v| from_lua(v?))
+                        .collect::<mlua::Result<_>>()?,
+                )
+            }
+        }
+        other => {
+            return Err(mlua::Error::runtime(format!(
+                "unsupported return type {other:?}"
+            )));
+        }
+    })
+}